@@ -0,0 +1,435 @@
+/**
+ * Application configuration read from the resource directory.
+ */
+
+use std::{fs, io::Read, path::Path};
+
+/// Name of the config file inside the resource directory.
+pub const CONFIG_FILE: &str = "app.toml";
+
+/// Value of `auto_promote` that asks with the promotion dialog every time.
+pub const AUTO_PROMOTE_OFF: &str = "off";
+
+const DEFAULT_TITLE: &str = "Schack";
+const DEFAULT_ICON: &str = "icon.png";
+/// First bytes of every PNG file.
+const PNG_SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', b'\r', b'\n', 0x1a, b'\n'];
+
+/// User configurable settings, all with sensible defaults.
+#[derive(PartialEq)]
+pub struct AppConfig {
+    pub title: String,
+    /// Icon file name, relative to the resource directory, `None` if there is no usable icon.
+    pub icon: Option<String>,
+    /// Linear (smooth) rather than nearest-neighbour filtering when scaling sprites.
+    pub smooth_sprites: bool,
+    /// Offer a draw claim in dead positions, see `rules::is_dead_position` for what is detected.
+    pub detect_dead_positions: bool,
+    /// Let a draw be claimed when the same position comes up for the third time.
+    pub repetition_draws: bool,
+    /// Let a draw be claimed after 50 moves by each side without a capture or pawn move.
+    pub fifty_move_draws: bool,
+    /// Warn in the HUD when a draw by the 50-move rule or threefold repetition is close to becoming claimable.
+    pub draw_warnings: bool,
+    /// Teaching overlays such as the promotion rank guide.
+    pub beginner_hints: bool,
+    /// Pieces drop onto the board when a game starts.
+    pub intro_animation: bool,
+    /// Faintly show the moves of the friendly piece under the cursor.
+    pub hover_preview: bool,
+    /// Panel with the number of pieces of each kind on the board.
+    pub piece_counts: bool,
+    /// Colour capture targets by whether the capturing piece would be left hanging, see `rules::capture_is_safe`.
+    pub capture_safety: bool,
+    /// Thin lines between the tiles of the board.
+    pub tile_lines: bool,
+    /// Colour of the lines between tiles, from a "#rrggbb" or "#rrggbbaa" string.
+    pub tile_line_colour: [f32; 4],
+    /// File letters along the bottom of the board and rank numbers along its left edge.
+    pub coordinate_labels: bool,
+    /// Grid lines and a coordinate in every tile, for following along on a stream.
+    pub grid_overlay: bool,
+    /// Colour of the grid overlay's lines, from a "#rrggbb" or "#rrggbbaa" string.
+    pub grid_line_colour: [f32; 4],
+    /// Height of the grid overlay's coordinates, in pixels.
+    pub grid_label_size: f32,
+    /// Teaching restriction: when off, castling is neither offered nor accepted, though the engine allows it.
+    pub allow_castling: bool,
+    /// Teaching restriction: when off, en passant is neither offered nor accepted, though the engine allows it.
+    pub allow_en_passant: bool,
+    /// Flip the board after every move so the side to move is at the bottom, for two players at one screen.
+    pub auto_flip: bool,
+    /// Turn the board to the side to move while stepping back through the game, whether or not `auto_flip` is on.
+    pub review_flip: bool,
+    /// Draw only the board, the pieces and the grid overlay, for clean screenshots.
+    pub board_only: bool,
+    /// Play the antichess variant: captures are mandatory, the king is an ordinary piece and
+    /// a side wins by running out of pieces or moves.
+    pub antichess: bool,
+    /// SAN letter of the piece every pawn reaching the last rank becomes without asking, one of "Q", "R", "B"
+    /// and "N", for drills such as knight promotion puzzles. `None`, or "off" in the file, asks with the
+    /// promotion dialog.
+    pub auto_promote: Option<char>,
+    /// Sound effects when a piece is lifted and put down.
+    pub sound: bool,
+    /// Volume of the sound effects, between 0 and 1.
+    pub volume: f32,
+}
+
+impl Default for AppConfig {
+    fn default() -> AppConfig {
+        AppConfig {
+            title: DEFAULT_TITLE.to_string(),
+            icon: Some(DEFAULT_ICON.to_string()),
+            smooth_sprites: true,
+            detect_dead_positions: false,
+            repetition_draws: true,
+            fifty_move_draws: true,
+            draw_warnings: true,
+            beginner_hints: false,
+            intro_animation: true,
+            hover_preview: false,
+            piece_counts: false,
+            capture_safety: false,
+            tile_lines: false,
+            tile_line_colour: [0.0, 0.0, 0.0, 0.6],
+            coordinate_labels: true,
+            grid_overlay: false,
+            grid_line_colour: [1.0, 1.0, 1.0, 0.5],
+            grid_label_size: 16.0,
+            allow_castling: true,
+            allow_en_passant: true,
+            auto_flip: false,
+            review_flip: false,
+            board_only: false,
+            antichess: false,
+            auto_promote: None,
+            sound: true,
+            volume: 0.5,
+        }
+    }
+}
+
+impl AppConfig {
+    /// Reads the config from `resource_dir`, falling back to defaults for a missing file or malformed values.
+    pub fn load(resource_dir: &Path) -> AppConfig {
+        let mut config = AppConfig::default();
+        let file = resource_dir.join(CONFIG_FILE);
+
+        if !file.exists() {
+            config.check_icon(resource_dir);
+            return config;
+        }
+
+        let table = match fs::read_to_string(&file).map_err(|e| e.to_string())
+            .and_then(|s| s.parse::<toml::Value>().map_err(|e| e.to_string())) {
+            Ok(t) => t,
+            Err(e) => {
+                eprintln!("Warning: could not read {}, using defaults ({})", file.display(), e);
+                config.check_icon(resource_dir);
+                return config;
+            }
+        };
+
+        if let Some(v) = table.get("title") {
+            match v.as_str() {
+                Some(s) => config.title = s.to_string(),
+                None => eprintln!("Warning: `title` in {} is not a string, using \"{}\"", file.display(), DEFAULT_TITLE),
+            }
+        }
+
+        if let Some(v) = table.get("icon") {
+            match v.as_str() {
+                Some(s) => config.icon = Some(s.trim_start_matches('/').to_string()),
+                None => eprintln!("Warning: `icon` in {} is not a string, using \"{}\"", file.display(), DEFAULT_ICON),
+            }
+        }
+
+        if let Some(b) = get_bool(&table, "smooth_sprites", &file) {
+            config.smooth_sprites = b;
+        }
+
+        if let Some(b) = get_bool(&table, "detect_dead_positions", &file) {
+            config.detect_dead_positions = b;
+        }
+
+        if let Some(b) = get_bool(&table, "repetition_draws", &file) {
+            config.repetition_draws = b;
+        }
+
+        if let Some(b) = get_bool(&table, "fifty_move_draws", &file) {
+            config.fifty_move_draws = b;
+        }
+
+        if let Some(b) = get_bool(&table, "draw_warnings", &file) {
+            config.draw_warnings = b;
+        }
+
+        if let Some(b) = get_bool(&table, "beginner_hints", &file) {
+            config.beginner_hints = b;
+        }
+
+        if let Some(b) = get_bool(&table, "intro_animation", &file) {
+            config.intro_animation = b;
+        }
+
+        if let Some(b) = get_bool(&table, "hover_preview", &file) {
+            config.hover_preview = b;
+        }
+
+        if let Some(b) = get_bool(&table, "piece_counts", &file) {
+            config.piece_counts = b;
+        }
+
+        if let Some(b) = get_bool(&table, "capture_safety", &file) {
+            config.capture_safety = b;
+        }
+
+        if let Some(b) = get_bool(&table, "tile_lines", &file) {
+            config.tile_lines = b;
+        }
+
+        if let Some(v) = table.get("tile_line_colour") {
+            match v.as_str().and_then(parse_hex_colour) {
+                Some(c) => config.tile_line_colour = c,
+                None => eprintln!("Warning: `tile_line_colour` in {} is not a \"#rrggbb\" colour, ignoring it", file.display()),
+            }
+        }
+
+        if let Some(b) = get_bool(&table, "coordinate_labels", &file) {
+            config.coordinate_labels = b;
+        }
+
+        if let Some(b) = get_bool(&table, "grid_overlay", &file) {
+            config.grid_overlay = b;
+        }
+
+        if let Some(v) = table.get("grid_line_colour") {
+            match v.as_str().and_then(parse_hex_colour) {
+                Some(c) => config.grid_line_colour = c,
+                None => eprintln!("Warning: `grid_line_colour` in {} is not a \"#rrggbb\" colour, ignoring it", file.display()),
+            }
+        }
+
+        if let Some(v) = table.get("grid_label_size") {
+            match v.as_float().or_else(|| v.as_integer().map(|i| i as f64)) {
+                Some(size) if size > 0.0 => config.grid_label_size = size as f32,
+                _ => eprintln!("Warning: `grid_label_size` in {} is not a positive number, ignoring it", file.display()),
+            }
+        }
+
+        if let Some(b) = get_bool(&table, "allow_castling", &file) {
+            config.allow_castling = b;
+        }
+
+        if let Some(b) = get_bool(&table, "allow_en_passant", &file) {
+            config.allow_en_passant = b;
+        }
+
+        if let Some(b) = get_bool(&table, "auto_flip", &file) {
+            config.auto_flip = b;
+        }
+
+        if let Some(b) = get_bool(&table, "review_flip", &file) {
+            config.review_flip = b;
+        }
+
+        if let Some(b) = get_bool(&table, "board_only", &file) {
+            config.board_only = b;
+        }
+
+        if let Some(b) = get_bool(&table, "antichess", &file) {
+            config.antichess = b;
+        }
+
+        if let Some(v) = table.get("auto_promote") {
+            match v.as_str() {
+                Some(AUTO_PROMOTE_OFF) => config.auto_promote = None,
+                Some(s) if s.len() == 1 && "QRBN".contains(s) => config.auto_promote = s.chars().next(),
+                _ => eprintln!("Warning: `auto_promote` in {} is not \"Q\", \"R\", \"B\", \"N\" or \"{}\", ignoring it",
+                    file.display(), AUTO_PROMOTE_OFF),
+            }
+        }
+
+        if let Some(b) = get_bool(&table, "sound", &file) {
+            config.sound = b;
+        }
+
+        if let Some(v) = table.get("volume") {
+            match v.as_float().or_else(|| v.as_integer().map(|i| i as f64)) {
+                Some(volume) if (0.0..=1.0).contains(&volume) => config.volume = volume as f32,
+                _ => eprintln!("Warning: `volume` in {} is not a number from 0 to 1, ignoring it", file.display()),
+            }
+        }
+
+        if let Some(icon) = config.icon.as_ref().filter(|icon| *icon != DEFAULT_ICON && !resource_dir.join(icon).is_file()) {
+            eprintln!("Warning: icon {} not found in {}, using \"{}\"", icon, resource_dir.display(), DEFAULT_ICON);
+            config.icon = Some(DEFAULT_ICON.to_string());
+        }
+        config.check_icon(resource_dir);
+
+        config
+    }
+
+    /// Drops the icon unless it is a readable PNG, as ggez panics on a missing or unsupported one.
+    fn check_icon(&mut self, resource_dir: &Path) {
+        let icon = match self.icon.as_ref() {
+            Some(icon) => resource_dir.join(icon),
+            None => return,
+        };
+        let mut signature = [0; 8];
+        let result = fs::File::open(&icon).and_then(|mut f| f.read_exact(&mut signature));
+        let problem = match result {
+            Err(e) => e.to_string(),
+            Ok(()) if signature != PNG_SIGNATURE => "not a PNG file".to_string(),
+            Ok(()) => return,
+        };
+        eprintln!("Warning: can't use icon {} ({}), starting without one", icon.display(), problem);
+        self.icon = None;
+    }
+
+    /// Writes a single setting back to the config file, keeping everything else in it.
+    pub fn save_setting(resource_dir: &Path, key: &str, value: toml::Value) {
+        let file = resource_dir.join(CONFIG_FILE);
+
+        let mut table = if file.exists() {
+            match fs::read_to_string(&file).ok().and_then(|s| s.parse::<toml::Value>().ok()) {
+                Some(toml::Value::Table(t)) => t,
+                _ => {
+                    // don't clobber a file the user has to fix by hand
+                    eprintln!("Warning: {} is malformed, not saving `{}`", file.display(), key);
+                    return;
+                }
+            }
+        } else {
+            toml::value::Table::new()
+        };
+
+        table.insert(key.to_string(), value);
+        if let Err(e) = fs::write(&file, toml::Value::Table(table).to_string()) {
+            eprintln!("Warning: could not save {} ({})", file.display(), e);
+        }
+    }
+
+    /// Icon path in the form ggez expects, i.e. rooted at the resource directory.
+    pub fn icon_path(&self) -> Option<String> {
+        self.icon.as_ref().map(|icon| format!("/{}", icon))
+    }
+}
+
+/// Reads a boolean setting, warning if it has the wrong type.
+fn get_bool(table: &toml::Value, key: &str, file: &Path) -> Option<bool> {
+    let value = table.get(key)?;
+    if value.as_bool().is_none() {
+        eprintln!("Warning: `{}` in {} is not a boolean, ignoring it", key, file.display());
+    }
+    value.as_bool()
+}
+
+/// Parses "#rrggbb" or "#rrggbbaa" into colour components between 0 and 1.
+fn parse_hex_colour(s: &str) -> Option<[f32; 4]> {
+    let hex = s.strip_prefix('#')?;
+    if !(hex.len() == 6 || hex.len() == 8) || !hex.is_ascii() {
+        return None;
+    }
+    let mut colour = [1.0; 4];
+    for (i, component) in colour.iter_mut().enumerate().take(hex.len() / 2) {
+        *component = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).ok()? as f32 / 255.0;
+    }
+    Some(colour)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::temp_dir;
+
+    /// Icon left after checking a file with `contents`, or no file at all for `None`.
+    fn checked_icon(name: &str, contents: Option<&[u8]>) -> Option<String> {
+        let dir = temp_dir(name);
+        if let Some(contents) = contents {
+            fs::write(dir.join(DEFAULT_ICON), contents).unwrap();
+        }
+        let mut config = AppConfig::default();
+        config.check_icon(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+        config.icon
+    }
+
+    #[test]
+    fn missing_icon_is_dropped() {
+        assert_eq!(checked_icon("icon_missing", None), None);
+    }
+
+    #[test]
+    fn icon_that_is_not_a_png_is_dropped() {
+        assert_eq!(checked_icon("icon_not_png", Some(b"GIF89a, not a PNG at all")), None);
+    }
+
+    #[test]
+    fn png_icon_is_kept() {
+        let mut png = PNG_SIGNATURE.to_vec();
+        png.extend_from_slice(b"rest of the file");
+        assert_eq!(checked_icon("icon_png", Some(&png)), Some(DEFAULT_ICON.to_string()));
+    }
+
+    /// Automatic promotion piece loaded from a config file holding `contents`.
+    fn loaded_auto_promote(name: &str, contents: &str) -> Option<char> {
+        let dir = temp_dir(name);
+        fs::write(dir.join(CONFIG_FILE), contents).unwrap();
+        let config = AppConfig::load(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+        config.auto_promote
+    }
+
+    #[test]
+    fn auto_promote_piece() {
+        assert_eq!(loaded_auto_promote("auto_promote_knight", "auto_promote = \"N\"\n"), Some('N'));
+        assert_eq!(loaded_auto_promote("auto_promote_off", "auto_promote = \"off\"\n"), None);
+        assert_eq!(loaded_auto_promote("auto_promote_king", "auto_promote = \"K\"\n"), None);
+    }
+
+    #[test]
+    fn auto_promote_is_saved() {
+        let dir = temp_dir("auto_promote_saved");
+        AppConfig::save_setting(&dir, "auto_promote", "R".into());
+        assert_eq!(AppConfig::load(&dir).auto_promote, Some('R'));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn volume_out_of_range_is_ignored() {
+        let dir = temp_dir("volume");
+        fs::write(dir.join(CONFIG_FILE), "volume = 0.25\n").unwrap();
+        assert_eq!(AppConfig::load(&dir).volume, 0.25);
+        fs::write(dir.join(CONFIG_FILE), "volume = 2\n").unwrap();
+        assert_eq!(AppConfig::load(&dir).volume, AppConfig::default().volume);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn example_config_holds_the_defaults() {
+        let dir = temp_dir("example_config");
+        fs::write(dir.join(CONFIG_FILE), include_str!("../resources/app.toml.example")).unwrap();
+        let loaded = AppConfig::load(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+        let expected = AppConfig {
+            // there is no icon in the temporary directory
+            icon: None,
+            // the nearest a hex alpha comes to one half
+            grid_line_colour: [1.0, 1.0, 1.0, 128.0 / 255.0],
+            ..AppConfig::default()
+        };
+        assert!(loaded == expected);
+    }
+
+    #[test]
+    fn hex_colours() {
+        assert_eq!(parse_hex_colour("#ff0000"), Some([1.0, 0.0, 0.0, 1.0]));
+        assert_eq!(parse_hex_colour("#00ff0080"), Some([0.0, 1.0, 0.0, 128.0 / 255.0]));
+        assert_eq!(parse_hex_colour("ff0000"), None);
+        assert_eq!(parse_hex_colour("#ff00"), None);
+        assert_eq!(parse_hex_colour("#gg0000"), None);
+        assert_eq!(parse_hex_colour("#ff0é0"), None);
+    }
+}
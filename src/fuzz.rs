@@ -0,0 +1,57 @@
+/**
+ * Fuzzing of the engine with random games, checking the board stays consistent after every move.
+ */
+
+use crate::{get_colour, move_string, rng::XorShift, rules};
+use murnion_chess::Game;
+
+/// Games longer than this are given up on rather than played to the end.
+const MAX_PLIES: usize = 500;
+
+/// Plays `games` random games to completion, returning whether all of them stayed consistent.
+/// The moves of the first failing game are printed.
+pub fn run(games: u32) -> bool {
+    let mut rng = XorShift::from_clock();
+    for game_number in 1..=games {
+        let mut game = Game::new();
+        let mut moves = Vec::new();
+
+        while moves.len() < MAX_PLIES {
+            let legal = legal_moves(&game);
+            if legal.is_empty() {
+                break;
+            }
+            let (from, to) = legal[rng.below(legal.len())];
+            let string = move_string(from, to);
+            moves.push(string.clone());
+
+            let mover = game.current_turn;
+            game.take_turn(string);
+            let result = if game.current_turn == mover {
+                Err("the engine rejected a legal move, or didn't pass the turn on".to_string())
+            } else {
+                rules::check_board(&game.board)
+            };
+            if let Err(e) = result {
+                eprintln!("Game {} failed after {} moves: {}", game_number, moves.len(), e);
+                eprintln!("Moves: {}", moves.join(", "));
+                return false;
+            }
+        }
+        println!("Game {} passed, {} moves", game_number, moves.len());
+    }
+    true
+}
+
+/// Moves of the side to move, as the GUI offers them in standard chess.
+fn legal_moves(game: &Game) -> Vec<((usize, usize), (usize, usize))> {
+    let colour = game.current_turn;
+    rules::all_squares()
+        .filter(|&(rank, file)| get_colour(game.board[rank][file]) == Some(colour))
+        .flat_map(|from| {
+            rules::legal_moves(game, from, &rules::MoveRules::default())
+                .into_iter()
+                .map(move |to| (from, to))
+        })
+        .collect()
+}
@@ -0,0 +1,290 @@
+/**
+ * Keyboard key names and the configurable key bindings.
+ */
+
+use ggez::event::KeyCode;
+use std::{collections::{HashMap, HashSet}, fs, path::Path};
+
+/// Name of the key binding file inside the resource directory.
+pub const BINDINGS_FILE: &str = "keys.toml";
+
+/// Everything that can be bound to a key.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Action {
+    /// Drop the selection, or quit when nothing is selected.
+    Quit,
+    Reset,
+    ClaimDraw,
+    Analysis,
+    /// Enter free play, where either side may move, or go back to the game.
+    FreePlay,
+    Debug,
+    BeginnerHints,
+    SpriteFilter,
+    /// Select the next movable piece, or the previous one with shift.
+    CycleSelection,
+    /// Move the selected piece to the focused destination, or its first one if none is focused.
+    ConfirmMove,
+    /// Focus the next or previous legal destination of the selected piece.
+    NextDestination,
+    PreviousDestination,
+    /// Copy a text diagram of the board to the clipboard.
+    CopyBoard,
+    /// Copy the moves of the game to the clipboard on a single line.
+    CopyMoves,
+    /// Set up the position from a FEN on the clipboard.
+    PasteFen,
+    Fullscreen,
+    /// Freeze animations and the quiz timer, ignoring the board until unpaused.
+    Pause,
+    /// Start or stop the coordinate quiz.
+    Quiz,
+    /// Toggle the pieces on the quiz board.
+    QuizPieces,
+    /// Toggle colouring capture targets as safe or hanging.
+    CaptureSafety,
+    /// Toggle thin lines between the tiles.
+    TileLines,
+    /// Toggle grid lines and a coordinate in every tile.
+    GridOverlay,
+    /// Toggle shading the squares the side to move can reach, by how many pieces reach them.
+    Heatmap,
+    /// Remember the current board to compare later positions against.
+    Snapshot,
+    /// Toggle highlighting the squares that differ from the snapshot.
+    SnapshotDiff,
+    ClearSnapshot,
+    /// Toggle marking the squares where captures happened this game, with how many.
+    CaptureMarkers,
+    /// Turn the board around.
+    FlipBoard,
+    /// Toggle hiding everything but the board, the pieces and the grid overlay.
+    BoardOnly,
+    /// Toggle the panel counting the pieces on the board.
+    PieceCounts,
+    /// Start over from the next preset opening.
+    Opening,
+    /// Toggle the faint move preview for the hovered piece.
+    HoverPreview,
+    /// Step the piece pawns promote to without asking through each choice, then back to asking.
+    AutoPromote,
+    /// Step back through the positions of the game, looking at them without playing.
+    ReviewBack,
+    /// Step forward through the positions of the game, back to playing after the last one.
+    ReviewForward,
+    /// Turn the sound effects off or back on.
+    Mute,
+    VolumeDown,
+    VolumeUp,
+}
+
+/// Action names as used in the binding file, with their default keys.
+const DEFAULT_BINDINGS: [(Action, &str, KeyCode); 37] = [
+    (Action::Quit, "quit", KeyCode::Escape),
+    (Action::Reset, "reset", KeyCode::R),
+    (Action::ClaimDraw, "claim_draw", KeyCode::C),
+    (Action::Analysis, "analysis", KeyCode::A),
+    (Action::FreePlay, "free_play", KeyCode::E),
+    (Action::Debug, "debug", KeyCode::D),
+    (Action::BeginnerHints, "beginner_hints", KeyCode::H),
+    (Action::SpriteFilter, "sprite_filter", KeyCode::F),
+    (Action::CycleSelection, "cycle_selection", KeyCode::Tab),
+    (Action::ConfirmMove, "confirm_move", KeyCode::Return),
+    (Action::NextDestination, "next_destination", KeyCode::Right),
+    (Action::PreviousDestination, "previous_destination", KeyCode::Left),
+    (Action::CopyBoard, "copy_board", KeyCode::B),
+    (Action::CopyMoves, "copy_moves", KeyCode::J),
+    (Action::PasteFen, "paste_fen", KeyCode::L),
+    (Action::HoverPreview, "hover_preview", KeyCode::P),
+    (Action::Opening, "opening", KeyCode::O),
+    (Action::PieceCounts, "piece_counts", KeyCode::N),
+    (Action::Fullscreen, "fullscreen", KeyCode::F11),
+    (Action::GridOverlay, "grid_overlay", KeyCode::G),
+    (Action::TileLines, "tile_lines", KeyCode::T),
+    (Action::CaptureSafety, "capture_safety", KeyCode::K),
+    (Action::Pause, "pause", KeyCode::Space),
+    (Action::Quiz, "quiz", KeyCode::Q),
+    (Action::QuizPieces, "quiz_pieces", KeyCode::W),
+    (Action::Heatmap, "heatmap", KeyCode::M),
+    (Action::Snapshot, "snapshot", KeyCode::S),
+    (Action::SnapshotDiff, "snapshot_diff", KeyCode::V),
+    (Action::ClearSnapshot, "clear_snapshot", KeyCode::X),
+    (Action::CaptureMarkers, "capture_markers", KeyCode::U),
+    (Action::BoardOnly, "board_only", KeyCode::Z),
+    (Action::AutoPromote, "auto_promote", KeyCode::I),
+    (Action::FlipBoard, "flip_board", KeyCode::Y),
+    (Action::ReviewBack, "review_back", KeyCode::PageUp),
+    (Action::ReviewForward, "review_forward", KeyCode::PageDown),
+    (Action::Mute, "mute", KeyCode::Key0),
+    (Action::VolumeDown, "volume_down", KeyCode::Minus),
+    (Action::VolumeUp, "volume_up", KeyCode::Equals),
+];
+
+/// Which action each key triggers.
+pub struct KeyBindings {
+    actions: HashMap<KeyCode, Action>,
+}
+
+impl KeyBindings {
+    /// Reads bindings from `resource_dir`, keeping the default key for any action that isn't bound there.
+    pub fn load(resource_dir: &Path) -> KeyBindings {
+        let mut keys = DEFAULT_BINDINGS.iter().map(|(action, _, key)| (*action, *key)).collect::<HashMap<Action, KeyCode>>();
+        let mut explicit = HashSet::new();
+        let file = resource_dir.join(BINDINGS_FILE);
+
+        if file.exists() {
+            match fs::read_to_string(&file).map_err(|e| e.to_string())
+                .and_then(|s| s.parse::<toml::Value>().map_err(|e| e.to_string())) {
+                Ok(table) => {
+                    for (action, name, _) in DEFAULT_BINDINGS.iter() {
+                        if let Some(v) = table.get(*name) {
+                            match v.as_str().and_then(key_from_name) {
+                                Some(key) => {
+                                    keys.insert(*action, key);
+                                    explicit.insert(*action);
+                                },
+                                None => eprintln!("Warning: unknown key {} for `{}` in {}, using the default", v, name, file.display()),
+                            }
+                        }
+                    }
+                },
+                Err(e) => eprintln!("Warning: could not read {}, using default keys ({})", file.display(), e),
+            }
+        }
+
+        // keys bound in the file take precedence over defaults, which are left unbound when they clash;
+        // between two bindings of the same kind the first action in DEFAULT_BINDINGS order keeps the key
+        let mut actions = HashMap::new();
+        let (bound, defaults): (Vec<&(Action, &str, KeyCode)>, Vec<_>) = DEFAULT_BINDINGS.iter().partition(|(action, _, _)| explicit.contains(action));
+        for (action, name, _) in bound.into_iter().chain(defaults) {
+            let key = keys[action];
+            if let Some(other) = actions.get(&key) {
+                let other_name = DEFAULT_BINDINGS.iter().find(|(a, _, _)| a == other).map_or("", |(_, n, _)| *n);
+                if explicit.contains(action) {
+                    eprintln!("Warning: {} is bound to both `{}` and `{}`, keeping `{}`",
+                        key_name(key).unwrap_or("key"), other_name, name, other_name);
+                } else {
+                    eprintln!("Warning: {} is bound to `{}`, leaving `{}` without its default key",
+                        key_name(key).unwrap_or("key"), other_name, name);
+                }
+            } else {
+                actions.insert(key, *action);
+            }
+        }
+
+        KeyBindings { actions }
+    }
+
+    pub fn action(&self, key: KeyCode) -> Option<Action> {
+        self.actions.get(&key).copied()
+    }
+}
+
+/// Keys that can be referred to by name, using the same names as `KeyCode`.
+const KEY_NAMES: [(&str, KeyCode); 67] = [
+    ("A", KeyCode::A),
+    ("B", KeyCode::B),
+    ("C", KeyCode::C),
+    ("D", KeyCode::D),
+    ("E", KeyCode::E),
+    ("F", KeyCode::F),
+    ("G", KeyCode::G),
+    ("H", KeyCode::H),
+    ("I", KeyCode::I),
+    ("J", KeyCode::J),
+    ("K", KeyCode::K),
+    ("L", KeyCode::L),
+    ("M", KeyCode::M),
+    ("N", KeyCode::N),
+    ("O", KeyCode::O),
+    ("P", KeyCode::P),
+    ("Q", KeyCode::Q),
+    ("R", KeyCode::R),
+    ("S", KeyCode::S),
+    ("T", KeyCode::T),
+    ("U", KeyCode::U),
+    ("V", KeyCode::V),
+    ("W", KeyCode::W),
+    ("X", KeyCode::X),
+    ("Y", KeyCode::Y),
+    ("Z", KeyCode::Z),
+    ("Key0", KeyCode::Key0),
+    ("Key1", KeyCode::Key1),
+    ("Key2", KeyCode::Key2),
+    ("Key3", KeyCode::Key3),
+    ("Key4", KeyCode::Key4),
+    ("Key5", KeyCode::Key5),
+    ("Key6", KeyCode::Key6),
+    ("Key7", KeyCode::Key7),
+    ("Key8", KeyCode::Key8),
+    ("Key9", KeyCode::Key9),
+    ("F1", KeyCode::F1),
+    ("F2", KeyCode::F2),
+    ("F3", KeyCode::F3),
+    ("F4", KeyCode::F4),
+    ("F5", KeyCode::F5),
+    ("F6", KeyCode::F6),
+    ("F7", KeyCode::F7),
+    ("F8", KeyCode::F8),
+    ("F9", KeyCode::F9),
+    ("F10", KeyCode::F10),
+    ("F11", KeyCode::F11),
+    ("F12", KeyCode::F12),
+    ("Escape", KeyCode::Escape),
+    ("Tab", KeyCode::Tab),
+    ("Return", KeyCode::Return),
+    ("Space", KeyCode::Space),
+    ("Back", KeyCode::Back),
+    ("Delete", KeyCode::Delete),
+    ("Left", KeyCode::Left),
+    ("Right", KeyCode::Right),
+    ("Up", KeyCode::Up),
+    ("Down", KeyCode::Down),
+    ("Home", KeyCode::Home),
+    ("End", KeyCode::End),
+    ("PageUp", KeyCode::PageUp),
+    ("PageDown", KeyCode::PageDown),
+    ("Minus", KeyCode::Minus),
+    ("Equals", KeyCode::Equals),
+    ("Comma", KeyCode::Comma),
+    ("Period", KeyCode::Period),
+    ("Slash", KeyCode::Slash)
+];
+
+pub fn key_name(key: KeyCode) -> Option<&'static str> {
+    KEY_NAMES.iter().find(|(_, k)| *k == key).map(|(name, _)| *name)
+}
+
+pub fn key_from_name(name: &str) -> Option<KeyCode> {
+    KEY_NAMES.iter().find(|(n, _)| n.eq_ignore_ascii_case(name)).map(|(_, key)| *key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::temp_dir;
+
+    /// Bindings loaded from a temporary resource directory holding `contents` as the binding file.
+    fn load_from(name: &str, contents: &str) -> KeyBindings {
+        let dir = temp_dir(name);
+        fs::write(dir.join(BINDINGS_FILE), contents).unwrap();
+        let bindings = KeyBindings::load(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+        bindings
+    }
+
+    #[test]
+    fn user_binding_wins_over_an_earlier_default() {
+        // reset comes before flip_board in DEFAULT_BINDINGS and has R by default
+        let bindings = load_from("keys_rebind", "flip_board = \"R\"\n");
+        assert!(bindings.action(KeyCode::R) == Some(Action::FlipBoard));
+        assert!(bindings.action(KeyCode::Y).is_none());
+        assert!(bindings.action(KeyCode::Escape) == Some(Action::Quit));
+    }
+
+    #[test]
+    fn first_of_two_user_bindings_keeps_the_key() {
+        let bindings = load_from("keys_clash", "reset = \"K\"\nflip_board = \"K\"\n");
+        assert!(bindings.action(KeyCode::K) == Some(Action::Reset));
+    }
+}
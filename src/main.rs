@@ -4,94 +4,556 @@
  * Last updated: 2021-10-03
  */
 
-use ggez::{conf, event, graphics, ContextBuilder, Context, GameError, GameResult};
-use std::{path, env, collections::HashMap};
+use ggez::{conf, event, graphics, graphics::spritebatch::SpriteBatch, ContextBuilder, Context, GameError, GameResult};
+use std::{path, env, time, cell::RefCell, collections::{HashMap, VecDeque}};
 use murnion_chess::{Game, Colour, Piece};
+use linked_hash_map::LinkedHashMap;
+
+mod config;
+mod fuzz;
+mod keys;
+mod notation;
+mod quiz;
+mod recording;
+mod rng;
+mod rules;
+mod sound;
+mod stats;
+#[cfg(test)]
+mod testing;
 
 /// A chess board is 8x8 tiles.
 const GRID_SIZE: i16 = 8;
 /// Sutible size of each tile.
 const GRID_CELL_SIZE: (i16, i16) = (90, 90);
 
+/// Height of the status strip below the board.
+const HUD_HEIGHT: f32 = 40.0;
+
 /// Size of the application window.
 const SCREEN_SIZE: (f32, f32) = (
     GRID_SIZE as f32 * GRID_CELL_SIZE.0 as f32,
-    GRID_SIZE as f32 * GRID_CELL_SIZE.1 as f32,
+    GRID_SIZE as f32 * GRID_CELL_SIZE.1 as f32 + HUD_HEIGHT,
 );
 
+/// Colour of the bars around the board when the window doesn't have its shape.
+const LETTERBOX: graphics::Color = graphics::Color::new(0.0, 0.0, 0.0, 1.0);
+
+/// How long selection highlights take to fade in or out, in seconds.
+const HIGHLIGHT_FADE: f32 = 0.08;
+
+/// How long a notice, such as a rejected FEN, stays in the HUD.
+const NOTICE_DURATION: time::Duration = time::Duration::from_millis(4000);
+
+/// How long the en passant hint stays up, fading out towards the end.
+const TOAST_DURATION: time::Duration = time::Duration::from_millis(2000);
+
+/// How far the selected piece is raised off its tile, and how long it takes to rise or settle.
+const LIFT_HEIGHT: f32 = 4.0;
+const LIFT_DURATION: f32 = 0.08;
+
+/// How long the pieces take to drop onto the board when a game starts.
+const INTRO_DURATION: f32 = 0.5;
+
+/// How long a completed move stays in the HUD.
+const HUD_MOVE_DURATION: time::Duration = time::Duration::from_millis(2000);
+
+/// Sprite for each piece, relative to the resource directory.
+const SPRITE_FILES: [(Piece, &str); 12] = [
+    (Piece::King(Colour::Black), "/black_king.png"),
+    (Piece::Queen(Colour::Black), "/black_queen.png"),
+    (Piece::Rook(Colour::Black), "/black_rook.png"),
+    (Piece::Pawn(Colour::Black), "/black_pawn.png"),
+    (Piece::Bishop(Colour::Black), "/black_bishop.png"),
+    (Piece::Knight(Colour::Black), "/black_knight.png"),
+    (Piece::King(Colour::White), "/white_king.png"),
+    (Piece::Queen(Colour::White), "/white_queen.png"),
+    (Piece::Rook(Colour::White), "/white_rook.png"),
+    (Piece::Pawn(Colour::White), "/white_pawn.png"),
+    (Piece::Bishop(Colour::White), "/white_bishop.png"),
+    (Piece::Knight(Colour::White), "/white_knight.png")
+];
+
 // GUI Color representations
 const BLACK: graphics::Color = graphics::Color::new(228.0/255.0, 196.0/255.0, 108.0/255.0, 1.0);
 const WHITE: graphics::Color = graphics::Color::new(188.0/255.0, 140.0/255.0, 76.0/255.0, 1.0);
+const LAST_MOVE: graphics::Color = graphics::Color::new(230.0/255.0, 200.0/255.0, 40.0/255.0, 0.4);
 const SELECTED: graphics::Color = graphics::Color::new(0.0/255.0, 140.0/255.0, 10.0/255.0, 0.8);
 const HIGHLIGHTED: graphics::Color = graphics::Color::new(0.0/255.0, 140.0/255.0, 10.0/255.0, 0.3);
+const HOVER_PREVIEW: graphics::Color = graphics::Color::new(0.0/255.0, 140.0/255.0, 10.0/255.0, 0.4);
+const LIFT_SHADOW: graphics::Color = graphics::Color::new(0.0, 0.0, 0.0, 0.3);
+const CASTLING_TARGET: graphics::Color = graphics::Color::new(30.0/255.0, 90.0/255.0, 220.0/255.0, 0.45);
+const FOCUSED_DESTINATION: graphics::Color = graphics::Color::new(1.0, 1.0, 1.0, 0.9);
+const CASTLING_PREVIEW: graphics::Color = graphics::Color::new(1.0, 1.0, 1.0, 0.5);
+const CAPTURE_TARGET: graphics::Color = graphics::Color::new(0.0/255.0, 140.0/255.0, 10.0/255.0, 0.9);
+const CAPTURE_SAFE: graphics::Color = graphics::Color::new(30.0/255.0, 200.0/255.0, 60.0/255.0, 0.9);
+const CAPTURE_HANGING: graphics::Color = graphics::Color::new(220.0/255.0, 30.0/255.0, 30.0/255.0, 0.9);
+const EN_PASSANT_LABEL: graphics::Color = graphics::Color::new(1.0, 1.0, 1.0, 0.6);
+const THREATENED: graphics::Color = graphics::Color::new(200.0/255.0, 20.0/255.0, 20.0/255.0, 0.9);
+const ATTACKER: graphics::Color = graphics::Color::new(200.0/255.0, 20.0/255.0, 20.0/255.0, 0.5);
+const NO_MOVES: graphics::Color = graphics::Color::new(200.0/255.0, 20.0/255.0, 20.0/255.0, 0.5);
+const HUD_BACKGROUND: graphics::Color = graphics::Color::new(0.15, 0.15, 0.15, 1.0);
+const HUD_TEXT: graphics::Color = graphics::Color::new(0.9, 0.9, 0.9, 1.0);
+const CAPTURE_MARKER: graphics::Color = graphics::Color::new(200.0/255.0, 30.0/255.0, 30.0/255.0, 0.85);
+/// Mobility heatmap colour, its opacity scaled by how many pieces reach a square.
+const HEATMAP: graphics::Color = graphics::Color::new(240.0/255.0, 110.0/255.0, 20.0/255.0, 0.6);
+/// Number of pieces reaching a square at which the heatmap is fully opaque.
+const HEATMAP_SATURATION: u32 = 4;
+/// Colours of the pinned move overlays in analysis mode, used in turn.
+const ANALYSIS_COLOURS: [graphics::Color; 4] = [
+    graphics::Color::new(30.0/255.0, 90.0/255.0, 220.0/255.0, 0.35),
+    graphics::Color::new(220.0/255.0, 120.0/255.0, 20.0/255.0, 0.35),
+    graphics::Color::new(150.0/255.0, 40.0/255.0, 200.0/255.0, 0.35),
+    graphics::Color::new(20.0/255.0, 170.0/255.0, 170.0/255.0, 0.35),
+];
+const TOAST_BACKGROUND: graphics::Color = graphics::Color::new(0.0, 0.0, 0.0, 0.7);
+const DIFF_ADDED: graphics::Color = graphics::Color::new(30.0/255.0, 200.0/255.0, 60.0/255.0, 0.45);
+const DIFF_REMOVED: graphics::Color = graphics::Color::new(200.0/255.0, 20.0/255.0, 20.0/255.0, 0.45);
+const DIFF_CHANGED: graphics::Color = graphics::Color::new(230.0/255.0, 170.0/255.0, 20.0/255.0, 0.45);
+const LINE_GUIDE: graphics::Color = graphics::Color::new(1.0, 1.0, 1.0, 0.12);
+const PIN_ICON: graphics::Color = graphics::Color::new(220.0/255.0, 40.0/255.0, 40.0/255.0, 0.9);
+const PAUSE_DIM: graphics::Color = graphics::Color::new(0.0, 0.0, 0.0, 0.6);
+const QUIZ_RIGHT: graphics::Color = graphics::Color::new(30.0/255.0, 200.0/255.0, 60.0/255.0, 0.6);
+const QUIZ_WRONG: graphics::Color = graphics::Color::new(220.0/255.0, 30.0/255.0, 30.0/255.0, 0.6);
+const PROMOTION_GUIDE: graphics::Color = graphics::Color::new(1.0, 1.0, 1.0, 0.35);
+const PROMOTION_BACKGROUND: graphics::Color = graphics::Color::new(0.95, 0.95, 0.95, 0.95);
+const DEBUG_BACKGROUND: graphics::Color = graphics::Color::new(0.0, 0.0, 0.0, 0.6);
+const DEBUG_LIT: graphics::Color = graphics::Color::new(1.0, 1.0, 1.0, 1.0);
+const DEBUG_DIMMED: graphics::Color = graphics::Color::new(1.0, 1.0, 1.0, 0.25);
+const DEBUG_MARKER: graphics::Color = graphics::Color::new(200.0/255.0, 30.0/255.0, 200.0/255.0, 0.9);
+const FRAME_GRAPH_BAR: graphics::Color = graphics::Color::new(30.0/255.0, 200.0/255.0, 60.0/255.0, 0.9);
+
+/// Half-moves without a capture or pawn move after which the 50-move rule lets a draw be claimed.
+const FIFTY_MOVE_LIMIT: u32 = 100;
+/// Half-moves without a capture or pawn move from which the HUD warns of the 50-move rule.
+const FIFTY_MOVE_WARNING: u32 = 80;
+
+/// Number of move strings kept for the debug overlay.
+const DEBUG_MOVE_LOG_LENGTH: usize = 5;
+/// Number of frame durations in the debug frame-time graph.
+const FRAME_GRAPH_LENGTH: usize = 120;
+/// Frame duration drawn at the full height of the frame-time graph, in seconds.
+const FRAME_GRAPH_SCALE: f32 = 1.0 / 30.0;
+
+/// Pieces a pawn can promote to, in the order the promotion dialog lists them, with their SAN letters.
+const PROMOTION_CHOICES: [PromotionChoice; 4] = [(Piece::Queen, 'Q'), (Piece::Rook, 'R'), (Piece::Bishop, 'B'), (Piece::Knight, 'N')];
+/// Order of the piece kinds in the piece count panel.
+const PIECE_COUNT_ORDER: [fn(Colour) -> Piece; 6] = [Piece::King, Piece::Queen, Piece::Rook, Piece::Bishop, Piece::Knight, Piece::Pawn];
+
+/// Castling rights in FEN order: white kingside, white queenside, black kingside, black queenside.
+const CASTLING_SYMBOLS: [char; 4] = ['K', 'Q', 'k', 'q'];
+/// For each castling right, the home squares of the king and rook it depends on.
+const CASTLING_HOME_SQUARES: [[(usize, usize); 2]; 4] = [
+    [(7, 4), (7, 7)],
+    [(7, 4), (7, 0)],
+    [(0, 4), (0, 7)],
+    [(0, 4), (0, 0)],
+];
+
+/// Preset openings to drill, as the main line's moves from and to squares.
+const OPENINGS: [(&str, &[&str]); 5] = [
+    ("Italian Game", &["e2e4", "e7e5", "g1f3", "b8c6", "f1c4"]),
+    ("Ruy Lopez", &["e2e4", "e7e5", "g1f3", "b8c6", "f1b5"]),
+    ("Sicilian Defence", &["e2e4", "c7c5"]),
+    ("French Defence", &["e2e4", "e7e6", "d2d4", "d7d5"]),
+    ("Queen's Gambit", &["d2d4", "d7d5", "c2c4"]),
+];
+
+const USAGE: &str = "\
+Usage: schack_gui [options]
+
+Options:
+    --fen \"<fen>\"      start from the given position
+    --record <file>    record all input events to a file
+    --replay <file>    play back input events recorded with --record
+    -h, --help         show this help";
+
+/// Command line options.
+#[derive(Default)]
+struct CliArgs {
+    /// Record all input events to this file.
+    record: Option<path::PathBuf>,
+    /// Play back input events from this file.
+    replay: Option<path::PathBuf>,
+    /// Start from this position instead of the standard one.
+    fen: Option<String>,
+    /// Play this many random games without a window, checking the engine, for contributors.
+    fuzz: Option<u32>,
+}
+
+impl CliArgs {
+    fn parse() -> CliArgs {
+        let mut cli = CliArgs::default();
+        let mut args = env::args().skip(1);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--record" => cli.record = args.next().map(path::PathBuf::from),
+                "--replay" => cli.replay = args.next().map(path::PathBuf::from),
+                "--fen" => cli.fen = args.next(),
+                "--fuzz" => match args.next().and_then(|n| n.parse().ok()) {
+                    Some(n) => cli.fuzz = Some(n),
+                    None => eprintln!("Warning: --fuzz needs a number of games"),
+                },
+                "--help" | "-h" => {
+                    println!("{}", USAGE);
+                    std::process::exit(0);
+                },
+                _ => eprintln!("Warning: ignoring unknown argument {}", arg),
+            }
+        }
+        cli
+    }
+}
+
+/// What makes two positions the same for repetition: the pieces, whether white is to move, castling rights and
+/// the en passant square.
+type PositionKey = ([[Piece; 8]; 8], bool, [bool; 4], Option<(usize, usize)>);
+
+/// Legal moves worked out per square for the current position.
+type MoveCache = RefCell<HashMap<(usize, usize), Vec<(usize, usize)>>>;
+
+/// The real game, put aside while exploring in free play.
+struct SavedGame {
+    game: Game,
+    castling_rights: [bool; 4],
+    move_history: Vec<String>,
+    moves_played: u32,
+    halfmove_clock: u32,
+    position_counts: HashMap<PositionKey, u32>,
+    last_move: Option<((usize,usize), (usize,usize))>,
+    positions: Vec<(rules::Board, Colour)>,
+}
+
+/// The game put aside while stepping back through its earlier positions.
+struct Review {
+    game: Game,
+    last_move: Option<((usize,usize), (usize,usize))>,
+    /// Orientation of the board before the review, turned back to when it ends.
+    flipped: bool,
+    /// Position shown, indexing `AppState::positions`.
+    index: usize,
+}
+
+/// How a game has ended.
+#[derive(Clone, Copy, PartialEq)]
+enum TerminalKind {
+    /// The side to move is mated, the colour is the winner.
+    Checkmate(Colour),
+    Stalemate,
+    /// Draw claimed in a position where neither side can mate.
+    DeadPosition,
+    /// Draw claimed after 50 moves by each side without a capture or pawn move.
+    FiftyMoveRule,
+    /// Draw claimed when the same position has come up for the third time.
+    Repetition,
+    /// In antichess, the side to move has no pieces or moves left and so wins, the colour is the winner.
+    NoMovesLeft(Colour),
+}
+
+/// Kind of piece a pawn can promote to, with its SAN letter.
+type PromotionChoice = (fn(Colour) -> Piece, char);
+
+/// Squares a move went from and to, and when it was made.
+type CompletedMove = ((usize, usize), (usize, usize), time::Instant);
+
+/// What the GUI shows over the position on the board: the selection, overlays and running animations.
+/// All of it refers to the squares of one position, so it is dropped together when the board is replaced.
+#[derive(Default)]
+struct BoardUi {
+    selected_square: Option<(usize,usize)>,
+    highlighted_squares: Vec<(usize,usize)>,
+    /// Position in `highlighted_squares` of the destination chosen with the arrow keys.
+    focused_destination: Option<usize>,
+    /// Enemy pieces attacking the selected piece.
+    threats: Vec<(usize,usize)>,
+    /// Destination of the selected pawn's promoting move, while the promotion dialog asks what it becomes.
+    promotion_pending: Option<(usize,usize)>,
+    /// Position in `PROMOTION_CHOICES` of the piece chosen with the arrow keys in the promotion dialog.
+    promotion_focus: usize,
+    /// Position in `movable_squares` when selecting with Tab.
+    cycle_index: Option<usize>,
+    analysis_overlays: LinkedHashMap<(usize,usize), Vec<(usize,usize)>>,
+    /// Moves of the hovered piece, recomputed only when the hovered square changes.
+    hover_moves: Vec<(usize,usize)>,
+    /// Square of the piece raised by being selected, which settles again once dropped or placed.
+    lift_square: Option<(usize,usize)>,
+    lift_offset: f32,
+    /// Opacity of the selection highlights while they fade in, from 0 to 1.
+    selection_alpha: f32,
+    /// Highlights of the previous selection while they fade out, with their opacity.
+    fading_tiles: Vec<((usize,usize), graphics::Color)>,
+    fading_alpha: f32,
+    /// Distance above its tile each piece is drawn while dropping in, purely cosmetic.
+    drop_offsets: [[f32; 8]; 8],
+}
+
+impl BoardUi {
+    /// Cuts every animation short and forgets the selection and overlays, for a board that is about to be replaced.
+    fn reset(&mut self) {
+        *self = BoardUi::default();
+    }
+}
 
 /// GUI logic and event implementation structure. 
 struct AppState {
-    sprites: Vec<(Piece, graphics::Image)>,
+    sprites: HashMap<Piece, graphics::Image>,
+    /// Scale that fits each sprite to a tile, so sprite sets of any resolution line up.
+    sprite_scales: HashMap<Piece, f32>,
+    /// One batch per sprite, so all pieces of a kind are drawn in a single call.
+    piece_batches: HashMap<Piece, SpriteBatch>,
+    /// All 64 tiles, built on the first draw rather than every frame, and again once the tile lines change.
+    board_mesh: Option<graphics::Mesh>,
+    /// Draw lines between the tiles, toggled with T, in `tile_line_colour`.
+    tile_lines: bool,
+    tile_line_colour: graphics::Color,
+    resource_dir: path::PathBuf,
+    /// Window title from the config, followed by the game's status.
+    title: String,
+    /// Status last put in the window title, so it is only set again when it changes.
+    title_status: String,
+    key_bindings: keys::KeyBindings,
+    /// Linear instead of nearest-neighbour sprite scaling, toggled with F.
+    smooth_sprites: bool,
     game: Game,
-    // Save piece positions, which tiles has been clicked, current colour, etc...
-    selected_square: Option<(usize,usize)>,
-    highlighted_squares: Vec<(usize,usize)>
+    /// Selection, overlays and animations tied to the position on the board.
+    ui: BoardUi,
+    /// Moves of each piece in the current position, filled in as they are asked for and cleared when the board changes.
+    move_cache: MoveCache,
+    /// Colour capture targets as safe or hanging, toggled with K.
+    capture_safety: bool,
+    /// Grid lines and a coordinate in every tile, toggled with G.
+    grid_overlay: bool,
+    grid_line_colour: graphics::Color,
+    grid_label_size: f32,
+    /// Shade the squares the side to move can reach, toggled with M.
+    show_heatmap: bool,
+    /// Captures made on each square in the current game, outside free play.
+    captures: [[u32; 8]; 8],
+    show_capture_markers: bool,
+    coordinate_labels: bool,
+    /// Board turned 180 degrees, black at the bottom.
+    flipped: bool,
+    /// Flip the board after every move so the side to move is at the bottom.
+    auto_flip: bool,
+    /// Flip the board to the side to move while stepping through a review, independently of `auto_flip`.
+    review_flip: bool,
+    /// Hide the HUD, panels and overlays, keeping only the board, pieces and grid overlay.
+    board_only: bool,
+    /// Number of the side to move's pieces reaching each square, computed when the heatmap is first drawn in a position.
+    mobility: Option<[[u32; 8]; 8]>,
+    /// Castling rights, indexed like `CASTLING_SYMBOLS`, kept up to date from the moves played.
+    castling_rights: [bool; 4],
+    debug: bool,
+    /// Moves of the current game in standard algebraic notation.
+    move_history: Vec<String>,
+    /// Latest strings passed to `take_turn` and whether they were accepted, newest last.
+    move_log: VecDeque<(String, bool)>,
+    /// Latest frame durations in seconds, newest last, for the debug frame-time graph.
+    frame_times: VecDeque<f32>,
+    detect_dead_positions: bool,
+    /// Draw claimed with C, by the rule it was claimed under.
+    draw_claimed: Option<TerminalKind>,
+    stats: stats::Stats,
+    /// Teaching restrictions on the special moves, see `AppConfig::allow_castling`.
+    allow_castling: bool,
+    allow_en_passant: bool,
+    /// Antichess rules instead of standard chess, see `AppConfig::antichess`.
+    antichess: bool,
+    /// Half-moves played in the current game.
+    moves_played: u32,
+    /// Half-moves since the last capture or pawn move, for the 50-move rule.
+    halfmove_clock: u32,
+    /// How often each position has occurred in the current game, for threefold repetition.
+    position_counts: HashMap<PositionKey, u32>,
+    /// Which of the draw rules that are claimed rather than automatic apply, see `AppConfig::repetition_draws`.
+    repetition_draws: bool,
+    fifty_move_draws: bool,
+    draw_warnings: bool,
+    /// Index in `OPENINGS` of the preset the current game started from.
+    opening: Option<usize>,
+    /// Whether the current game's result has been counted in `stats`.
+    result_recorded: bool,
+    /// Piece letter every promotion picks without the dialog, stepped through with I, see `AppConfig::auto_promote`.
+    auto_promote: Option<char>,
+    /// Lift and drop effects, `None` if they couldn't be loaded or there is no window, as in tests.
+    sounds: Option<sound::Sounds>,
+    /// Sound effects on, toggled with 0.
+    sound: bool,
+    /// Volume of the sound effects, between 0 and 1, changed with - and =.
+    volume: f32,
+    /// Teaching overlays, toggled with H.
+    beginner_hints: bool,
+    /// Paused with Space, freezing animations and ignoring the board.
+    paused: bool,
+    /// Coordinate quiz round, started with Q, during which clicks answer instead of moving.
+    quiz: Option<quiz::Quiz>,
+    /// The real game while exploring in free play, entered and left with E.
+    free_play: Option<SavedGame>,
+    /// The real game while stepping back through it with PageUp and PageDown.
+    review: Option<Review>,
+    /// Pieces and side to move of each position of the game so far, starting with the one it started from.
+    positions: Vec<(rules::Board, Colour)>,
+    /// Analysis mode, toggled with A, where clicked pieces keep their move overlays.
+    analysis: bool,
+    /// Board remembered with S, to compare later positions against.
+    snapshot: Option<rules::Board>,
+    /// Highlight the squares that differ from the snapshot, toggled with V.
+    show_snapshot_diff: bool,
+    hover_square: Option<(usize,usize)>,
+    /// Last cursor position in board units, to find the hovered square again when the board turns under it.
+    hover_point: Option<(f32, f32)>,
+    /// Show the piece count panel, toggled with N.
+    show_piece_counts: bool,
+    /// Number of each piece on the board, counted once per move rather than every frame.
+    piece_counts: HashMap<Piece, usize>,
+    /// Show the moves of the hovered piece, toggled with P.
+    hover_preview: bool,
+    intro_animation: bool,
+    recorder: Option<recording::Recorder>,
+    playback: Option<recording::Playback>,
+    /// When a double pawn push last gave the opponent an en passant capture, for the beginner hint.
+    en_passant_toast: Option<time::Instant>,
+    /// Message for the user and when it was given, shown in the HUD before anything else.
+    notice: Option<(String, time::Instant)>,
+    /// Squares the most recent move went from and to, tinted until the next move.
+    last_move: Option<((usize,usize), (usize,usize))>,
+    /// Last completed move and when it was made, shown briefly in the HUD.
+    completed_move: Option<CompletedMove>,
+    fullscreen: bool,
+    /// Offset of the board from the window's top left corner, in board units.
+    view_origin: (f32, f32),
+    /// Window pixels per board unit, so tiles stay square at any window shape.
+    view_scale: f32
 }
 
 impl AppState {
     /// Initialise new application, i.e. initialise new game and load resources.
-    fn new(ctx: &mut Context) -> GameResult<AppState> {
+    fn new(ctx: &mut Context, config: &config::AppConfig, resource_dir: path::PathBuf) -> GameResult<AppState> {
+        let filter = sprite_filter(config.smooth_sprites);
+        let sprites = AppState::load_sprites(&resource_dir, |_path| {
+            let mut image = graphics::Image::new(ctx, _path)?;
+            image.set_filter(filter);
+            Ok(image)
+        })?;
+        let mut state = AppState::with_sprites(sprites, config, resource_dir);
+        state.sounds = sound::Sounds::load(ctx);
+        Ok(state)
+    }
 
-        
-        let state = AppState {
-            sprites: AppState::load_sprites(ctx),
+    /// Initialise new game with sprites already loaded, the part of `new` that needs no window.
+    fn with_sprites(sprites: HashMap<Piece, graphics::Image>, config: &config::AppConfig, resource_dir: path::PathBuf) -> AppState {
+        let stats = stats::Stats::load(&resource_dir);
+        let mut state = AppState {
+            piece_batches: build_piece_batches(&sprites),
+            sprite_scales: sprite_scales(&sprites),
+            sprites,
+            board_mesh: None,
+            tile_lines: config.tile_lines,
+            tile_line_colour: config.tile_line_colour.into(),
+            key_bindings: keys::KeyBindings::load(&resource_dir),
+            resource_dir,
+            title: config.title.clone(),
+            title_status: String::new(),
+            smooth_sprites: config.smooth_sprites,
             game: Game::new(),
-            selected_square: None,
-            highlighted_squares: vec![]
+            ui: BoardUi::default(),
+            move_cache: RefCell::new(HashMap::new()),
+            capture_safety: config.capture_safety,
+            grid_overlay: config.grid_overlay,
+            grid_line_colour: config.grid_line_colour.into(),
+            grid_label_size: config.grid_label_size,
+            show_heatmap: false,
+            captures: [[0; 8]; 8],
+            show_capture_markers: false,
+            coordinate_labels: config.coordinate_labels,
+            flipped: false,
+            auto_flip: config.auto_flip,
+            review_flip: config.review_flip,
+            board_only: config.board_only,
+            mobility: None,
+            castling_rights: [true; 4],
+            debug: false,
+            move_history: Vec::new(),
+            move_log: VecDeque::with_capacity(DEBUG_MOVE_LOG_LENGTH),
+            frame_times: VecDeque::with_capacity(FRAME_GRAPH_LENGTH),
+            detect_dead_positions: config.detect_dead_positions,
+            draw_claimed: None,
+            stats,
+            allow_castling: config.allow_castling,
+            allow_en_passant: config.allow_en_passant,
+            antichess: config.antichess,
+            moves_played: 0,
+            halfmove_clock: 0,
+            position_counts: HashMap::new(),
+            repetition_draws: config.repetition_draws,
+            fifty_move_draws: config.fifty_move_draws,
+            draw_warnings: config.draw_warnings,
+            opening: None,
+            result_recorded: false,
+            auto_promote: config.auto_promote,
+            sounds: None,
+            sound: config.sound,
+            volume: config.volume,
+            beginner_hints: config.beginner_hints,
+            paused: false,
+            quiz: None,
+            free_play: None,
+            review: None,
+            positions: Vec::new(),
+            analysis: false,
+            snapshot: None,
+            show_snapshot_diff: false,
+            hover_square: None,
+            hover_point: None,
+            show_piece_counts: config.piece_counts,
+            piece_counts: HashMap::new(),
+            hover_preview: config.hover_preview,
+            intro_animation: config.intro_animation,
+            recorder: None,
+            playback: None,
+            en_passant_toast: None,
+            notice: None,
+            last_move: None,
+            completed_move: None,
+            fullscreen: false,
+            view_origin: (0.0, 0.0),
+            view_scale: 1.0
         };
 
-        Ok(state)
-    }
+        state.start_intro();
+        state.count_pieces();
+        state.reset_draw_tracking();
 
-    /// Loads chess piese images into vector.
-    fn load_sprites(ctx: &mut Context) -> Vec<(Piece, graphics::Image)> {
-
-        [
-            (Piece::King(Colour::Black), "/black_king.png".to_string()),
-            (Piece::Queen(Colour::Black), "/black_queen.png".to_string()),
-            (Piece::Rook(Colour::Black), "/black_rook.png".to_string()),
-            (Piece::Pawn(Colour::Black), "/black_pawn.png".to_string()),
-            (Piece::Bishop(Colour::Black), "/black_bishop.png".to_string()),
-            (Piece::Knight(Colour::Black), "/black_knight.png".to_string()),
-            (Piece::King(Colour::White), "/white_king.png".to_string()),
-            (Piece::Queen(Colour::White), "/white_queen.png".to_string()),
-            (Piece::Rook(Colour::White), "/white_rook.png".to_string()),
-            (Piece::Pawn(Colour::White), "/white_pawn.png".to_string()),
-            (Piece::Bishop(Colour::White), "/white_bishop.png".to_string()),
-            (Piece::Knight(Colour::White), "/white_knight.png".to_string())
-        ]
-        .iter()
-        .map(|(_piece, _path)| (*_piece, graphics::Image::new(ctx, _path).unwrap()))
-        .collect::<Vec<(Piece, graphics::Image)>>()
+        state
     }
-}
 
-impl event::EventHandler<GameError> for AppState {
+    /// Loads chess piese images into a map with `load`, failing before loading any if one is missing from `resource_dir`.
+    fn load_sprites(resource_dir: &path::Path, mut load: impl FnMut(&str) -> GameResult<graphics::Image>) -> GameResult<HashMap<Piece, graphics::Image>> {
+        check_sprites(resource_dir)?;
 
-    /// For updating game logic, which front-end doesn't handle.
-    fn update(&mut self, _ctx: &mut Context) -> GameResult {
-        Ok(())
+        SPRITE_FILES
+        .iter()
+        .map(|(_piece, _path)| Ok((*_piece, load(_path)?)))
+        .collect::<GameResult<HashMap<Piece, graphics::Image>>>()
     }
 
-    /// Draw interface, i.e. draw game board
-    fn draw(&mut self, ctx: &mut Context) -> GameResult {
-
-        // clear interface with gray background colour
-        graphics::clear(ctx, [0.5, 0.5, 0.5, 1.0].into());
-
+    /// Draw parameters placing a piece's sprite centred in a square of `size` with its top left corner at `pos`.
+    fn sprite_param(&self, piece: Piece, pos: (f32, f32), size: f32) -> graphics::DrawParam {
+        let dimensions = self.sprites.get(&piece)
+            .map_or((GRID_CELL_SIZE.0 as f32, GRID_CELL_SIZE.1 as f32), |image| (image.width() as f32, image.height() as f32));
+        let (scale, dest) = sprite_placement(dimensions, self.sprite_scales.get(&piece).copied().unwrap_or(1.0), pos, size);
+        graphics::DrawParam::default()
+            .scale([scale, scale])
+            .dest([dest.0, dest.1])
+    }
 
-        // draw grid
+    /// Builds the tiles of the board into one mesh, to be rebuilt only if the tile size or colours change.
+    /// Lines between the tiles are drawn in `lines` if given, only inside the board so its border isn't doubled.
+    fn build_board_mesh(ctx: &mut Context, lines: Option<graphics::Color>) -> GameResult<graphics::Mesh> {
+        let mut builder = graphics::MeshBuilder::new();
         for _row in 0..8 {
             for _col in 0..8 {
-
-                // draw tile
-                let rectangle = graphics::Mesh::new_rectangle(ctx, 
+                builder.rectangle(
                     graphics::DrawMode::fill(), 
                     graphics::Rect::new_i32(
                         _col * GRID_CELL_SIZE.0 as i32,
@@ -105,180 +567,2631 @@ impl event::EventHandler<GameError> for AppState {
                         _ => 
                             if _row % 2 == 0 { BLACK } 
                             else { WHITE },
-                    }).expect("Failed to create tile.");
-                graphics::draw(ctx, &rectangle, graphics::DrawParam::default()).expect("Failed to draw tiles.");
+                    })?;
+            }
+        }
+        if let Some(colour) = lines {
+            // one pixel wide, starting exactly on the tile edge
+            for i in 1..GRID_SIZE as i32 {
+                builder.rectangle(graphics::DrawMode::fill(),
+                    graphics::Rect::new_i32(i * GRID_CELL_SIZE.0 as i32, 0, 1, GRID_SIZE as i32 * GRID_CELL_SIZE.1 as i32),
+                    colour)?;
+                builder.rectangle(graphics::DrawMode::fill(),
+                    graphics::Rect::new_i32(0, i * GRID_CELL_SIZE.1 as i32, GRID_SIZE as i32 * GRID_CELL_SIZE.0 as i32, 1),
+                    colour)?;
+            }
+        }
+        builder.build(ctx)
+    }
 
-                // draw piece
-                if self.game.board[_row as usize][_col as usize] != Piece::Empty {
-                    graphics::draw(ctx, &self.sprites.get(self.sprites.iter().position(|p| p.0 == self.game.board[_row as usize][_col as usize]).unwrap()).unwrap().1, graphics::DrawParam::default()
-                        .scale([2.0, 2.0])  // Tile size is 90 pixels, while image sizes are 45 pixels.
-                        .dest(
-                            [_col as f32 * GRID_CELL_SIZE.0 as f32, _row as f32 * GRID_CELL_SIZE.1 as f32],
-                        )
-                    ).expect("Failed to draw piece.");
-                }
+    fn toggle_tile_lines(&mut self) {
+        self.tile_lines = !self.tile_lines;
+        self.board_mesh = None;
+        config::AppConfig::save_setting(&self.resource_dir, "tile_lines", self.tile_lines.into());
+    }
+
+    /// Plays a sound effect unless the sound is off.
+    fn play_sound(&mut self, effect: sound::Effect) {
+        if !self.sound {
+            return;
+        }
+        if let Some(sounds) = self.sounds.as_mut() {
+            sounds.play(effect, self.volume);
+        }
+    }
+
+    /// Turns the sound effects off or back on and remembers the choice.
+    fn toggle_sound(&mut self) {
+        self.sound = !self.sound;
+        config::AppConfig::save_setting(&self.resource_dir, "sound", self.sound.into());
+        let notice = if self.sound { "Sound on" } else { "Sound off" };
+        self.notice = Some((notice.to_string(), time::Instant::now()));
+    }
+
+    /// Turns the sound effects up or down a step and remembers the volume, turning the sound on if it was off.
+    fn step_volume(&mut self, up: bool) {
+        self.volume = sound::step_volume(self.volume, up);
+        self.sound = true;
+        // whole percent, rather than the nearest f32 written out in full
+        let volume = (f64::from(self.volume) * 100.0).round() / 100.0;
+        config::AppConfig::save_setting(&self.resource_dir, "volume", volume.into());
+        config::AppConfig::save_setting(&self.resource_dir, "sound", true.into());
+        self.notice = Some((format!("Volume {}%", (self.volume * 100.0).round()), time::Instant::now()));
+    }
+
+    /// Switches between smooth and crisp sprite scaling and remembers the choice.
+    fn toggle_sprite_filter(&mut self) {
+        self.smooth_sprites = !self.smooth_sprites;
+        for image in self.sprites.values_mut() {
+            image.set_filter(sprite_filter(self.smooth_sprites));
+        }
+        self.piece_batches = build_piece_batches(&self.sprites);
+        config::AppConfig::save_setting(&self.resource_dir, "smooth_sprites", self.smooth_sprites.into());
+    }
+
+    /// Steps automatic promotion from asking through each piece in the dialog's order and back, remembering it.
+    fn cycle_auto_promote(&mut self) {
+        let next = match self.auto_promote.and_then(|letter| PROMOTION_CHOICES.iter().position(|&(_, l)| l == letter)) {
+            None => Some(PROMOTION_CHOICES[0].1),
+            Some(i) => PROMOTION_CHOICES.get(i + 1).map(|&(_, l)| l),
+        };
+        self.auto_promote = next;
+        let value = next.map_or(config::AUTO_PROMOTE_OFF.to_string(), |letter| letter.to_string());
+        config::AppConfig::save_setting(&self.resource_dir, "auto_promote", value.into());
+
+        let notice = match next {
+            Some('Q') => "Pawns promote to a queen without asking",
+            Some('R') => "Pawns promote to a rook without asking",
+            Some('B') => "Pawns promote to a bishop without asking",
+            Some(_) => "Pawns promote to a knight without asking",
+            None => "Promotion asks which piece",
+        };
+        self.notice = Some((notice.to_string(), time::Instant::now()));
+    }
+
+    /// Valid moves for the piece on `pos`, empty unless it belongs to the current player.
+    fn valid_moves(&self, pos: (usize, usize)) -> Vec<(usize, usize)> {
+        if self.draw_claimed.is_some() {
+            return Vec::new();
+        }
+        match get_colour(self.game.board[pos.0][pos.1]) {
+            Some(c) if c == self.game.current_turn => self.piece_moves(pos),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Valid moves for the piece on `pos` as if it were its side's turn.
+    fn piece_moves(&self, pos: (usize, usize)) -> Vec<(usize, usize)> {
+        let moves = self.cached_moves(pos);
+        match get_colour(self.game.board[pos.0][pos.1]) {
+            // in antichess a side that can capture must
+            Some(c) if self.antichess && self.can_capture(c) => rules::captures_only(&self.game.board, pos, moves),
+            _ => moves,
+        }
+    }
+
+    /// Moves for the piece on `pos` before the antichess capture rule, worked out once per position.
+    fn cached_moves(&self, pos: (usize, usize)) -> Vec<(usize, usize)> {
+        self.move_cache.borrow_mut()
+            .entry(pos)
+            .or_insert_with(|| self.generate_piece_moves(pos))
+            .clone()
+    }
+
+    /// Whether any of `colour`'s pieces can capture something.
+    fn can_capture(&self, colour: Colour) -> bool {
+        rules::all_squares()
+            .filter(|&pos| get_colour(self.game.board[pos.0][pos.1]) == Some(colour))
+            .any(|pos| self.cached_moves(pos).into_iter().any(|to| rules::captured_square(&self.game.board, pos, to).is_some()))
+    }
+
+    /// Forgets everything worked out from the previous position.
+    fn invalidate_moves(&mut self) {
+        self.move_cache.get_mut().clear();
+        self.mobility = None;
+    }
+
+    fn mobility_counts(&self) -> [[u32; 8]; 8] {
+        let mut counts = [[0; 8]; 8];
+        for (rank, file) in rules::all_squares() {
+            for to in self.valid_moves((rank, file)) {
+                counts[to.0][to.1] += 1;
             }
         }
+        counts
+    }
 
-        if let Some(s) = self.selected_square {
-            // draw selected square
-            let rectangle = graphics::Mesh::new_rectangle(ctx, 
-                graphics::DrawMode::fill(), 
-                graphics::Rect::new_i32(
-                    s.1 as i32 * GRID_CELL_SIZE.0 as i32,
-                    s.0 as i32 * GRID_CELL_SIZE.1 as i32,
-                    GRID_CELL_SIZE.0 as i32,
-                    GRID_CELL_SIZE.1 as i32,
-                ), 
-                SELECTED
-                ).expect("Failed to create tile.");
-            graphics::draw(ctx, &rectangle, graphics::DrawParam::default()).expect("Failed to draw tiles.");
+    fn generate_piece_moves(&self, pos: (usize, usize)) -> Vec<(usize, usize)> {
+        let move_rules = rules::MoveRules {
+            antichess: self.antichess,
+            allow_castling: self.allow_castling,
+            allow_en_passant: self.allow_en_passant,
+            castling_rights: self.castling_rights,
+        };
+        rules::legal_moves(&self.game, pos, &move_rules)
+    }
 
-            // draw highlighted squares
-            for squ in self.highlighted_squares.iter() {
-                let rectangle = graphics::Mesh::new_rectangle(ctx, 
-                    graphics::DrawMode::fill(), 
-                    graphics::Rect::new_i32(
-                        squ.1 as i32 * GRID_CELL_SIZE.0 as i32,
-                        squ.0 as i32 * GRID_CELL_SIZE.1 as i32,
-                        GRID_CELL_SIZE.0 as i32,
-                        GRID_CELL_SIZE.1 as i32,
-                    ), 
-                    HIGHLIGHTED
-                    ).expect("Failed to create tile.");
-                graphics::draw(ctx, &rectangle, graphics::DrawParam::default()).expect("Failed to draw tiles.");
+    /// Draw the players may claim in the current position. A dead position counts only when enabled in the config.
+    /// Like the FIDE rules, threefold repetition and the 50-move rule give the right to claim a draw rather than
+    /// ending the game themselves, each only while it is switched on in the config.
+    fn claimable_draw(&self) -> Option<TerminalKind> {
+        if self.antichess || self.free_play.is_some() || self.draw_claimed.is_some() || self.movable_squares().is_empty() {
+            return None;
+        }
+        if self.detect_dead_positions && rules::is_dead_position(&self.game.board) {
+            return Some(TerminalKind::DeadPosition);
+        }
+        let repeats = if self.repetition_draws { self.position_counts.get(&self.position_key()).copied().unwrap_or(0) } else { 0 };
+        let halfmove_clock = if self.fifty_move_draws { self.halfmove_clock } else { 0 };
+        rule_draw(repeats, halfmove_clock)
+    }
+
+    /// Adds the move overlay of the piece on `pos` in analysis mode, or removes it if already shown.
+    fn toggle_analysis_overlay(&mut self, pos: (usize, usize)) {
+        if self.ui.analysis_overlays.remove(&pos).is_none() && self.game.board[pos.0][pos.1] != Piece::Empty {
+            let moves = self.piece_moves(pos);
+            self.ui.analysis_overlays.insert(pos, moves);
+        }
+    }
+
+    /// Highlighted moves of the selected piece on `from` that capture, with the square of the piece each one takes.
+    fn capture_targets(&self, from: (usize, usize)) -> Vec<((usize, usize), (usize, usize))> {
+        self.ui.highlighted_squares
+            .iter()
+            .filter_map(|&to| rules::captured_square(&self.game.board, from, to).map(|captured| (to, captured)))
+            .collect()
+    }
+
+    /// Whether the game is over, i.e. the side to move has no legal moves.
+    fn terminal_state(&self) -> Option<TerminalKind> {
+        if self.draw_claimed.is_some() {
+            return self.draw_claimed;
+        }
+        ending(&self.game.board, self.game.current_turn, !self.movable_squares().is_empty(), self.antichess)
+    }
+
+    /// Whose move it is, or how the game ended.
+    fn status_text(&self) -> &'static str {
+        match self.terminal_state() {
+            Some(TerminalKind::Checkmate(Colour::White)) | Some(TerminalKind::NoMovesLeft(Colour::White)) => "White wins",
+            Some(TerminalKind::Checkmate(Colour::Black)) | Some(TerminalKind::NoMovesLeft(Colour::Black)) => "Black wins",
+            Some(TerminalKind::Stalemate) | Some(TerminalKind::DeadPosition)
+            | Some(TerminalKind::FiftyMoveRule) | Some(TerminalKind::Repetition) => "Draw",
+            None if self.game.current_turn == Colour::White => "White to move",
+            None => "Black to move",
+        }
+    }
+
+    /// Result token of the game as in PGN: "1-0", "0-1", "1/2-1/2", or "*" while it is still going.
+    fn result_token(&self) -> &'static str {
+        match self.terminal_state() {
+            Some(TerminalKind::Checkmate(Colour::White)) | Some(TerminalKind::NoMovesLeft(Colour::White)) => "1-0",
+            Some(TerminalKind::Checkmate(Colour::Black)) | Some(TerminalKind::NoMovesLeft(Colour::Black)) => "0-1",
+            Some(TerminalKind::Stalemate) | Some(TerminalKind::DeadPosition)
+            | Some(TerminalKind::FiftyMoveRule) | Some(TerminalKind::Repetition) => "1/2-1/2",
+            None => "*",
+        }
+    }
+
+    /// Selects a square and highlights the moves of the piece on it.
+    fn select_square(&mut self, pos: (usize, usize)) {
+        // earlier positions are only looked at, moves are played in the game itself
+        if self.review.is_some() {
+            return;
+        }
+        self.fade_out_selection();
+        self.ui.selection_alpha = 0.0;
+        // only a piece that can move is raised, leaving any previous one to settle
+        let colour = get_colour(self.game.board[pos.0][pos.1]);
+        if colour.is_some() && (self.free_play.is_some() || colour == Some(self.game.current_turn)) {
+            self.ui.lift_square = Some(pos);
+            self.ui.lift_offset = 0.0;
+            self.play_sound(sound::Effect::Lift);
+        }
+        self.ui.promotion_pending = None;
+        self.ui.selected_square = Some(pos);
+        // free play lets either side move
+        self.ui.highlighted_squares = if self.free_play.is_some() { self.piece_moves(pos) } else { self.valid_moves(pos) };
+        self.ui.focused_destination = None;
+        self.ui.threats = match get_colour(self.game.board[pos.0][pos.1]) {
+            Some(c) if c == self.game.current_turn => rules::attackers(&self.game.board, pos, rules::opponent(c)),
+            _ => Vec::new(),
+        };
+    }
+
+    fn clear_selection(&mut self) {
+        self.fade_out_selection();
+        self.ui.selected_square = None;
+        self.ui.highlighted_squares = Vec::new();
+        self.ui.focused_destination = None;
+        self.ui.threats = Vec::new();
+        self.ui.promotion_pending = None;
+    }
+
+    /// Tiles coloured for the current selection: the selected square, then its destinations.
+    fn selection_tiles(&self) -> Vec<((usize,usize), graphics::Color)> {
+        let s = match self.ui.selected_square {
+            Some(s) => s,
+            None => return Vec::new(),
+        };
+        // castling destinations in their own colour
+        let is_king = matches!(self.game.board[s.0][s.1], Piece::King(_));
+        let mut tiles = vec![(s, SELECTED)];
+        for squ in self.ui.highlighted_squares.iter() {
+            tiles.push((*squ, if is_king && castling_index(s, *squ).is_some() { CASTLING_TARGET } else { HIGHLIGHTED }));
+        }
+        tiles
+    }
+
+    /// Hands the current highlights over to fade out, so a new selection can fade in straight away.
+    fn fade_out_selection(&mut self) {
+        if self.ui.selected_square.is_some() {
+            self.ui.fading_tiles = self.selection_tiles();
+            self.ui.fading_alpha = self.ui.selection_alpha;
+        }
+    }
+
+    fn record(&mut self, event: recording::InputEvent) {
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.record(event);
+        }
+    }
+
+    /// Writes out everything kept on disk before the application exits, however it is closed.
+    fn shutdown(&mut self) {
+        if let Some(recorder) = self.recorder.as_mut() {
+            recorder.flush();
+        }
+        self.stats.save(&self.resource_dir);
+    }
+
+    /// Feeds a recorded event to the same handler that received it originally.
+    fn replay_event(&mut self, ctx: &mut Context, event: recording::InputEvent) {
+        match event {
+            recording::InputEvent::MouseUp { button, x, y } => {
+                if let Some(b) = recording::button_from_name(&button) {
+                    <Self as event::EventHandler<GameError>>::mouse_button_up_event(self, ctx, b, x, y);
+                }
+            },
+            recording::InputEvent::MouseMotion { x, y } =>
+                <Self as event::EventHandler<GameError>>::mouse_motion_event(self, ctx, x, y, 0.0, 0.0),
+            recording::InputEvent::MouseWheel { y } =>
+                <Self as event::EventHandler<GameError>>::mouse_wheel_event(self, ctx, 0.0, y),
+            recording::InputEvent::KeyDown { key, shift } => {
+                match keys::key_from_name(&key) {
+                    Some(k) => {
+                        let mods = if shift { event::KeyMods::SHIFT } else { event::KeyMods::NONE };
+                        <Self as event::EventHandler<GameError>>::key_down_event(self, ctx, k, mods, false);
+                    },
+                    None => eprintln!("Warning: unknown key {} in recording", key),
+                }
+            },
+        }
+    }
+
+    /// Lifts every piece just above the window, to drop back onto its tile over `INTRO_DURATION`.
+    fn start_intro(&mut self) {
+        if !self.intro_animation {
+            return;
+        }
+        let screen_rows = (0..GRID_SIZE as usize).map(|_row| self.flip((_row, 0)).0).collect::<Vec<usize>>();
+        for (offsets, screen_row) in self.ui.drop_offsets.iter_mut().zip(screen_rows) {
+            for offset in offsets.iter_mut() {
+                *offset = (screen_row + 1) as f32 * GRID_CELL_SIZE.1 as f32;
             }
         }
-        
-        // render updated graphics
-        graphics::present(ctx).expect("Failed to update graphics.");
+    }
 
+    /// Sets up a position from FEN, leaving the current game as it is if the FEN doesn't parse.
+    fn load_fen(&mut self, fen: &str) -> Result<(), String> {
+        let position = notation::parse_fen(fen)?;
+        self.new_game();
+        self.game.board = position.board;
+        self.game.current_turn = position.to_move;
+        self.game.en_passant_square = position.en_passant;
+        self.castling_rights = position.castling;
+        self.invalidate_moves();
+        self.count_pieces();
+        self.reset_draw_tracking();
+        self.follow_turn();
+        self.update_hover_moves();
         Ok(())
     }
 
-    /// Update game on mouse click
-    fn mouse_button_up_event(&mut self, ctx: &mut Context, button: event::MouseButton, x: f32, y: f32) {
-        if button == event::MouseButton::Left {
-            /* check click position and update board accordingly */
-            let rank = (y / GRID_CELL_SIZE.1 as f32).floor() as usize;
-            let file = (x / GRID_CELL_SIZE.0 as f32).floor() as usize;
-            match self.selected_square {
-                Some(pos) => {
-                    if pos == (rank, file) {
-                        self.selected_square = None;
-                        self.highlighted_squares = Vec::new();
-                    } else if self.highlighted_squares.iter().any(|p| p == &(rank,file)) {
-                        self.game.take_turn(move_string(pos, (rank,file)));
-                        self.selected_square = None;
-                        self.highlighted_squares = Vec::new();
-                    } else {
-                        self.selected_square = Some((rank, file));
-                        self.highlighted_squares = Vec::new();
-                        if let Some(c) = get_colour(self.game.board[rank][file]) {
-                            if c == self.game.current_turn {
-                                self.highlighted_squares = self.game.board[rank][file].get_valid_moves( (rank, file), &self.game.board, self.game.en_passant_square, self.game.castlings, self.game.current_turn);
-                            };
-                        };
-                    }
-                },
-                None => {
-                    self.selected_square = Some((rank, file));
-                    self.highlighted_squares = Vec::new();
-                    if let Some(c) = get_colour(self.game.board[rank][file]) {
-                        if c == self.game.current_turn {
-                            self.highlighted_squares = self.game.board[rank][file].get_valid_moves( (rank, file), &self.game.board, self.game.en_passant_square, self.game.castlings, self.game.current_turn);
-                        };
-                    };
+    /// Puts the game aside and explores from its position, or goes back to it exactly as it was.
+    fn toggle_free_play(&mut self) {
+        self.leave_review();
+        match self.free_play.take() {
+            Some(saved) => {
+                self.game = saved.game;
+                self.castling_rights = saved.castling_rights;
+                self.move_history = saved.move_history;
+                self.moves_played = saved.moves_played;
+                self.halfmove_clock = saved.halfmove_clock;
+                self.position_counts = saved.position_counts;
+                self.last_move = saved.last_move;
+                self.positions = saved.positions;
+            },
+            None => {
+                // a fresh engine game set to the same position, so the real one stays untouched
+                let mut game = Game::new();
+                game.board = self.game.board;
+                game.current_turn = self.game.current_turn;
+                game.en_passant_square = self.game.en_passant_square;
+                game.castlings = self.game.castlings;
+                self.free_play = Some(SavedGame {
+                    game: std::mem::replace(&mut self.game, game),
+                    castling_rights: self.castling_rights,
+                    move_history: self.move_history.clone(),
+                    moves_played: self.moves_played,
+                    halfmove_clock: self.halfmove_clock,
+                    position_counts: self.position_counts.clone(),
+                    last_move: self.last_move,
+                    positions: self.positions.clone(),
+                });
+            },
+        }
+        // the game returned to may have the other side to move
+        self.follow_turn();
+        self.invalidate_moves();
+        self.count_pieces();
+        self.clear_selection();
+        self.ui.cycle_index = None;
+        self.completed_move = None;
+        self.update_hover_moves();
+    }
+
+    /// Steps one position back or forward through the game, putting the game aside on the first step back and
+    /// going back to it on stepping forward past its last position.
+    fn step_review(&mut self, backwards: bool) {
+        if self.free_play.is_some() {
+            self.notice = Some(("Leave free play to review the game".to_string(), time::Instant::now()));
+            return;
+        }
+        let last = self.positions.len().saturating_sub(1);
+        let index = match (self.review.as_ref().map(|r| r.index), backwards) {
+            (None, true) if last > 0 => last - 1,
+            (None, _) => return,
+            (Some(i), true) => i.saturating_sub(1),
+            (Some(i), false) if i + 1 < last => i + 1,
+            (Some(_), false) => {
+                self.leave_review();
+                return;
+            },
+        };
+
+        match self.review.as_mut() {
+            Some(review) => review.index = index,
+            None => {
+                self.review = Some(Review {
+                    game: std::mem::replace(&mut self.game, Game::new()),
+                    last_move: self.last_move.take(),
+                    flipped: self.flipped,
+                    index,
+                });
+            },
+        }
+        let (board, to_move) = self.positions[index];
+        self.game.board = board;
+        self.game.current_turn = to_move;
+        self.ui.reset();
+        self.follow_turn();
+        self.invalidate_moves();
+        self.count_pieces();
+        self.update_hover_moves();
+        let notice = if index == 0 {
+            "Reviewing the starting position".to_string()
+        } else {
+            format!("Reviewing after {} of {} half-moves", index, last)
+        };
+        self.notice = Some((notice, time::Instant::now()));
+    }
+
+    /// Goes back to the game from a review, turned the way it was before.
+    fn leave_review(&mut self) {
+        if let Some(review) = self.review.take() {
+            self.game = review.game;
+            self.last_move = review.last_move;
+            self.flipped = review.flipped;
+            self.ui.reset();
+            self.follow_turn();
+            self.invalidate_moves();
+            self.count_pieces();
+            self.update_hover_moves();
+        }
+    }
+
+    /// Sets up the position from a FEN on the clipboard, keeping the current one if there is none.
+    fn paste_fen(&mut self) {
+        let result = paste_from_clipboard().and_then(|text| self.load_fen(text.trim()));
+        let notice = match result {
+            Ok(()) => "Position loaded from the clipboard".to_string(),
+            Err(e) => format!("Not a usable FEN: {}", e),
+        };
+        self.notice = Some((notice, time::Instant::now()));
+    }
+
+    /// Starts over from the initial position, dropping everything that refers to the old board.
+    fn new_game(&mut self) {
+        self.game = Game::new();
+        self.free_play = None;
+        if let Some(review) = self.review.take() {
+            self.flipped = review.flipped;
+        }
+        self.follow_turn();
+        self.invalidate_moves();
+
+        // cut any animation short rather than play it out over the new board
+        self.ui.reset();
+        self.start_intro();
+
+        self.castling_rights = [true; 4];
+        self.draw_claimed = None;
+        self.completed_move = None;
+        self.last_move = None;
+        self.en_passant_toast = None;
+        self.moves_played = 0;
+        self.captures = [[0; 8]; 8];
+        self.opening = None;
+        self.result_recorded = false;
+        self.move_log.clear();
+        self.move_history.clear();
+        self.count_pieces();
+        self.reset_draw_tracking();
+        self.update_hover_moves();
+    }
+
+    fn position_key(&self) -> PositionKey {
+        (self.game.board, self.game.current_turn == Colour::White, self.castling_rights, self.game.en_passant_square)
+    }
+
+    /// Starts counting towards the 50-move rule and repetitions, and the positions to review, from the current position.
+    fn reset_draw_tracking(&mut self) {
+        self.halfmove_clock = 0;
+        self.position_counts.clear();
+        self.position_counts.insert(self.position_key(), 1);
+        self.positions = vec![(self.game.board, self.game.current_turn)];
+    }
+
+    /// Warning that a draw by the 50-move rule or repetition is about to become claimable, if one is.
+    fn draw_warning(&self) -> Option<String> {
+        if !self.draw_warnings {
+            return None;
+        }
+        if self.repetition_draws && self.position_counts.get(&self.position_key()).is_some_and(|&n| n >= 2) {
+            Some("Position repeated, once more and a draw can be claimed".to_string())
+        } else if self.fifty_move_draws && self.halfmove_clock >= FIFTY_MOVE_WARNING {
+            let moves = FIFTY_MOVE_LIMIT.saturating_sub(self.halfmove_clock).div_ceil(2);
+            Some(format!("Draw claimable in {} moves without a capture or pawn move", moves))
+        } else {
+            None
+        }
+    }
+
+    fn count_pieces(&mut self) {
+        self.piece_counts.clear();
+        for piece in self.game.board.iter().flat_map(|row| row.iter()) {
+            if *piece != Piece::Empty {
+                *self.piece_counts.entry(*piece).or_insert(0) += 1;
+            }
+        }
+    }
+
+    /// Starts over and plays the moves of a preset opening, stopping at the first one that isn't legal.
+    fn load_opening(&mut self, index: usize) {
+        let (name, moves) = OPENINGS[index];
+        self.new_game();
+        for m in moves.iter() {
+            let squares = (notation::square_from_algebraic(&m[..2]), notation::square_from_algebraic(&m[2..]));
+            match squares {
+                (Some(from), Some(to)) if self.valid_moves(from).contains(&to) => self.make_move(from, to, None),
+                _ => {
+                    eprintln!("Warning: move {} of {} is not legal, stopping there", m, name);
+                    break;
                 }
             }
         }
+        self.opening = Some(index);
+        self.completed_move = None;
     }
 
-    fn key_down_event(
-        &mut self,
-        ctx: &mut Context,
-        keycode: event::KeyCode,
-        _keymods: event::KeyMods,
-        _repeat: bool,
-    ) {
-        if keycode == event::KeyCode::Escape {
-            event::quit(ctx);
-        } else if keycode == event::KeyCode::R {
-            self.game = Game::new();
-            self.selected_square = None;
-            self.highlighted_squares = Vec::new();
+    /// Counts the result in the statistics the first time the game is found to be over.
+    fn check_game_over(&mut self) {
+        if self.result_recorded || self.free_play.is_some() {
+            return;
+        }
+        if let Some(kind) = self.terminal_state() {
+            let winner = match kind {
+                TerminalKind::Checkmate(c) | TerminalKind::NoMovesLeft(c) => Some(c),
+                TerminalKind::Stalemate | TerminalKind::DeadPosition | TerminalKind::FiftyMoveRule
+                    | TerminalKind::Repetition => None,
+            };
+            self.stats.record(winner, self.moves_played.div_ceil(2));
+            self.stats.save(&self.resource_dir);
+            self.result_recorded = true;
         }
     }
-}
 
-pub fn main() -> GameResult {
+    /// Fits the board and HUD into a window of the given size, centred with bars on the sides that are too long.
+    fn fit_view(&mut self, ctx: &mut Context, width: f32, height: f32) {
+        // a minimised window has no size to fit, keep the last view rather than scale it to nothing
+        let (origin, scale) = match letterbox(width, height) {
+            Some(view) => view,
+            None => return,
+        };
+        self.view_origin = origin;
+        self.view_scale = scale;
+        let visible = (width / scale, height / scale);
+        graphics::set_screen_coordinates(ctx, graphics::Rect::new(-self.view_origin.0, -self.view_origin.1, visible.0, visible.1))
+            .expect("Failed to set screen coordinates.");
+    }
 
-    let resource_dir = path::PathBuf::from("./resources");
+    /// Switches between windowed and fullscreen, the resize event that follows fits the board to the new size.
+    fn toggle_fullscreen(&mut self, ctx: &mut Context) {
+        let mode = if self.fullscreen { conf::FullscreenType::Windowed } else { conf::FullscreenType::Desktop };
+        match graphics::set_fullscreen(ctx, mode) {
+            Ok(()) => self.fullscreen = !self.fullscreen,
+            Err(e) => eprintln!("Warning: could not change fullscreen mode ({})", e),
+        }
+    }
 
-    let context_builder = ContextBuilder::new("schack", "viola")
-        .add_resource_path(resource_dir)        // Import image files to GGEZ
-        .window_setup(
-            conf::WindowSetup::default()  
-                .title("Schack")                // Set window title "Schack"
-                .icon("/icon.png")              // Set application icon
-        )
-        .window_mode(
-            conf::WindowMode::default()
-                .dimensions(SCREEN_SIZE.0, SCREEN_SIZE.1) // Set window dimensions
-                .resizable(false)               // Fixate window size
-        );
-    let (mut contex, mut event_loop) = context_builder.build().expect("Failed to build context.");
+    /// Converts window coordinates, as mouse events report them, to board coordinates.
+    fn window_to_board(&self, x: f32, y: f32) -> (f32, f32) {
+        window_to_board(x, y, self.view_origin, self.view_scale)
+    }
 
-    let state = AppState::new(&mut contex).expect("Failed to create state.");
-    event::run(contex, event_loop, state)       // Run window event loop
-}
+    /// Square under a point in screen coordinates, i.e. after `window_to_board`, if it is on the board.
+    fn screen_to_square(&self, x: f32, y: f32) -> Option<(usize, usize)> {
+        screen_to_square(x, y, self.flipped)
+    }
 
-fn get_colour(piece: Piece) -> Option<Colour> {
-    match piece {
-        Piece::King(c)
-        | Piece::Queen(c)
-        | Piece::Rook(c)
-        | Piece::Knight(c)
-        | Piece::Bishop(c)
-        | Piece::Pawn(c) => Some(c),
-        Piece::Empty => None,
+    /// Top left corner of a square's tile in screen coordinates.
+    fn square_to_screen(&self, square: (usize, usize)) -> (f32, f32) {
+        square_to_screen(square, self.flipped)
     }
-}
 
-fn move_string(_from: (usize, usize), _to: (usize, usize)) -> String  {
-    let mut string = String::new();
+    /// Turns a square around the centre of the board when it is flipped, see the free function `flip`.
+    fn flip(&self, square: (usize, usize)) -> (usize, usize) {
+        flip(square, self.flipped)
+    }
 
-    string.push(match _from.1 {
-        0 => 'a',
-        1 => 'b',
-        2 => 'c',
-        3 => 'd',
-        4 => 'e',
-        5 => 'f',
-        6 => 'g',
-        7 => 'h',
-        _ => panic!("File wrong")
-    });
-    match _from.0 {
-        0..=7 => string.push(char::from_digit(8 - _from.0 as u32, 10).unwrap()),
-        _ => panic!("Rank wrong"),
-    };
-    string.push(' ');
-    string.push(match _to.1 {
-        0 => 'a',
-        1 => 'b',
-        2 => 'c',
-        3 => 'd',
-        4 => 'e',
-        5 => 'f',
-        6 => 'g',
+    /// With auto flip on, turns the board so the side to move sits at the bottom, or with review flip on while
+    /// stepping through a review.
+    fn follow_turn(&mut self) {
+        let follow = if self.review.is_some() { self.review_flip } else { self.auto_flip };
+        if follow {
+            self.flipped = self.game.current_turn == Colour::Black;
+            self.refresh_hover_square();
+        }
+    }
+
+    /// Finds the square under the cursor again after the board has turned, leaving its moves to `update_hover_moves`.
+    fn refresh_hover_square(&mut self) {
+        self.hover_square = self.hover_point.and_then(|(x, y)| self.screen_to_square(x, y));
+    }
+
+    /// Plays the selected piece's move, first asking what to promote to if it takes a pawn to the last rank,
+    /// unless automatic promotion is on.
+    fn play_or_promote(&mut self, from: (usize, usize), to: (usize, usize)) {
+        if !matches!(self.game.board[from.0][from.1], Piece::Pawn(_)) || (to.0 != 0 && to.0 != 7) {
+            self.make_move(from, to, None);
+        } else if self.auto_promote.is_some() {
+            self.make_move(from, to, self.auto_promote);
+        } else {
+            self.ui.promotion_pending = Some(to);
+            self.ui.promotion_focus = 0;
+        }
+    }
+
+    /// Squares covered by the promotion dialog for a pawn promoting on `to`, running from it towards the
+    /// middle of the board, with the piece each one offers.
+    fn promotion_squares(&self, to: (usize, usize)) -> Vec<((usize, usize), PromotionChoice)> {
+        PROMOTION_CHOICES
+            .iter()
+            .enumerate()
+            .map(|(i, &choice)| ((if to.0 == 0 { i } else { 7 - i }, to.1), choice))
+            .collect()
+    }
+
+    /// Promotes with the piece on the clicked square of the promotion dialog, or cancels the promotion
+    /// when the click misses it.
+    fn answer_promotion(&mut self, to: (usize, usize), clicked: (usize, usize)) {
+        self.ui.promotion_pending = None;
+        let choice = self.promotion_squares(to).into_iter().find(|&(squ, _)| squ == clicked);
+        if let (Some(from), Some((_, (_, letter)))) = (self.ui.selected_square, choice) {
+            self.make_move(from, to, Some(letter));
+        }
+    }
+
+    /// Draws the promotion dialog: the four pieces the mover's pawn can become, in the file it promotes on.
+    fn draw_promotion_dialog(&self, ctx: &mut Context, to: (usize, usize)) {
+        let colour = match self.ui.selected_square.and_then(|s| get_colour(self.game.board[s.0][s.1])) {
+            Some(c) => c,
+            None => return,
+        };
+        for (i, (squ, (kind, _))) in self.promotion_squares(to).into_iter().enumerate() {
+            let (x, y) = self.square_to_screen(squ);
+            let rectangle = graphics::Mesh::new_rectangle(ctx,
+                graphics::DrawMode::fill(),
+                graphics::Rect::new(x, y, GRID_CELL_SIZE.0 as f32, GRID_CELL_SIZE.1 as f32),
+                PROMOTION_BACKGROUND
+                ).expect("Failed to create tile.");
+            graphics::draw(ctx, &rectangle, graphics::DrawParam::default()).expect("Failed to draw tiles.");
+            let piece = kind(colour);
+            if let Some(image) = self.sprites.get(&piece) {
+                graphics::draw(ctx, image, self.sprite_param(piece, (x, y), GRID_CELL_SIZE.0 as f32)).expect("Failed to draw piece.");
+            }
+            if i == self.ui.promotion_focus {
+                draw_outline(ctx, (x, y), 4.0, FOCUSED_DESTINATION);
+            }
+        }
+    }
+
+    /// Plays a move and resets the selection, `promotion` being the letter of the piece a promoting pawn becomes.
+    fn make_move(&mut self, from: (usize, usize), to: (usize, usize), promotion: Option<char>) {
+        // in free play it becomes the turn of whichever side is moving
+        if self.free_play.is_some() {
+            if let Some(c) = get_colour(self.game.board[from.0][from.1]) {
+                self.game.current_turn = c;
+            }
+        }
+
+        // SAN needs the board before the move, and every other piece of the kind that could go there
+        let piece = self.game.board[from.0][from.1];
+        let others = rules::all_squares()
+            .filter(|&pos| pos != from && self.game.board[pos.0][pos.1] == piece && self.valid_moves(pos).contains(&to))
+            .collect::<Vec<(usize, usize)>>();
+        let san = notation::san(&self.game.board, from, to, &others, promotion.unwrap_or('Q'));
+
+        let captures = rules::captured_square(&self.game.board, from, to).is_some();
+        let resets_clock = matches!(piece, Piece::Pawn(_)) || captures;
+
+        let string = turn_string(from, to, promotion);
+        let mover = self.game.current_turn;
+        self.game.take_turn(string.clone());
+        self.invalidate_moves();
+
+        // the engine passes the turn on only for moves it accepted
+        let accepted = self.game.current_turn != mover;
+        if self.debug {
+            println!("take_turn(\"{}\") {}", string, if accepted { "accepted" } else { "rejected" });
+        }
+        if self.move_log.len() == DEBUG_MOVE_LOG_LENGTH {
+            self.move_log.pop_front();
+        }
+        self.move_log.push_back((string, accepted));
+        if !accepted {
+            return;
+        }
+
+        self.castling_rights = castling_rights_after(self.castling_rights, from, to);
+
+        // antichess has no check, the king is just another piece
+        let suffix = if self.antichess {
+            ""
+        } else {
+            notation::check_suffix(&self.game.board, self.game.current_turn, !self.movable_squares().is_empty())
+        };
+        self.move_history.push(san + suffix);
+
+        // only hint at en passant when a pawn next to the pushed one can legally take it
+        self.en_passant_toast = None;
+        if let Some(ep) = self.game.en_passant_square {
+            let takers = [to.1.checked_sub(1), Some(to.1 + 1)].iter()
+                .filter_map(|&f| f.filter(|&f| f < GRID_SIZE as usize))
+                .filter(|&f| self.game.board[to.0][f] == Piece::Pawn(self.game.current_turn))
+                .any(|f| self.valid_moves((to.0, f)).contains(&ep));
+            if takers {
+                self.en_passant_toast = Some(time::Instant::now());
+            }
+        }
+
+        if captures && self.free_play.is_none() {
+            self.captures[to.0][to.1] += 1;
+        }
+        self.halfmove_clock = if resets_clock { 0 } else { self.halfmove_clock + 1 };
+        *self.position_counts.entry(self.position_key()).or_insert(0) += 1;
+        self.positions.push((self.game.board, self.game.current_turn));
+
+        self.count_pieces();
+        self.moves_played += 1;
+        self.completed_move = Some((from, to, time::Instant::now()));
+        self.last_move = Some((from, to));
+        self.clear_selection();
+        // the piece settles onto its new square
+        self.ui.lift_square = Some(to);
+        self.play_sound(sound::Effect::Drop);
+        self.follow_turn();
+        self.ui.cycle_index = None;
+        self.update_hover_moves();
+        self.check_game_over();
+    }
+
+    fn update_hover_moves(&mut self) {
+        self.ui.hover_moves = match self.hover_square {
+            Some(pos) if self.hover_preview => self.valid_moves(pos),
+            _ => Vec::new(),
+        };
+    }
+
+    /// Squares of the current player's pieces that can move, in board order.
+    fn movable_squares(&self) -> Vec<(usize, usize)> {
+        rules::all_squares()
+            .filter(|&pos| !self.valid_moves(pos).is_empty())
+            .collect()
+    }
+
+    /// Selects the next (or previous) movable piece.
+    fn cycle_selection(&mut self, backwards: bool) {
+        let squares = self.movable_squares();
+        if squares.is_empty() {
+            return;
+        }
+
+        let index = match self.ui.cycle_index {
+            Some(i) if backwards => (i + squares.len() - 1) % squares.len(),
+            Some(i) => (i + 1) % squares.len(),
+            None if backwards => squares.len() - 1,
+            None => 0,
+        };
+        self.ui.cycle_index = Some(index);
+        self.select_square(squares[index]);
+    }
+
+    /// Moves the keyboard focus through the legal destinations of the selected piece, or through the pieces
+    /// of the promotion dialog while it is open, wrapping around.
+    fn cycle_destination(&mut self, backwards: bool) {
+        if self.ui.promotion_pending.is_some() {
+            let count = PROMOTION_CHOICES.len();
+            let step = if backwards { count - 1 } else { 1 };
+            self.ui.promotion_focus = (self.ui.promotion_focus + step) % count;
+            return;
+        }
+
+        let count = self.ui.highlighted_squares.len();
+        if self.ui.selected_square.is_none() || count == 0 {
+            return;
+        }
+
+        self.ui.focused_destination = Some(match self.ui.focused_destination {
+            Some(i) if backwards => (i + count - 1) % count,
+            Some(i) => (i + 1) % count,
+            None if backwards => count - 1,
+            None => 0,
+        });
+    }
+
+    /// Moves the focus of the open promotion dialog by a wheel scroll of `y`, down like the Right key and up like Left.
+    fn scroll_promotion(&mut self, y: f32) {
+        if self.ui.promotion_pending.is_none() {
+            return;
+        }
+        if y < 0.0 {
+            self.cycle_destination(false);
+        } else if y > 0.0 {
+            self.cycle_destination(true);
+        }
+    }
+
+    /// Draws a faint line where the side to move's pawns enter the promotion rank.
+    fn draw_promotion_guide(&self, ctx: &mut Context) {
+        let row = match self.game.current_turn {
+            Colour::White => 1,
+            Colour::Black => GRID_SIZE - 1,
+        };
+        // the edge between the promotion rank and the one before it
+        let edge = if self.flipped { GRID_SIZE - row } else { row };
+        let y = edge as f32 * GRID_CELL_SIZE.1 as f32;
+        let line = graphics::Mesh::new_line(ctx,
+            &[[0.0, y], [GRID_SIZE as f32 * GRID_CELL_SIZE.0 as f32, y]],
+            3.0,
+            PROMOTION_GUIDE
+            ).expect("Failed to create guide.");
+        graphics::draw(ctx, &line, graphics::DrawParam::default()).expect("Failed to draw guide.");
+    }
+
+    /// Draws the file letters in the bottom corners of the bottom row of tiles and the rank numbers in the top
+    /// corners of the left column, each in the colour of the other kind of tile so it stands out.
+    fn draw_coordinate_labels(&self, ctx: &mut Context) {
+        let last = GRID_SIZE as usize - 1;
+        for i in 0..GRID_SIZE as usize {
+            self.draw_tile_label(ctx, (last, i), &file_label(i, self.flipped).to_string(), true);
+            self.draw_tile_label(ctx, (i, 0), &rank_label(i, self.flipped).to_string(), false);
+        }
+    }
+
+    /// Draws a label in the tile on screen row and column `tile`, in its bottom right corner or top left one.
+    fn draw_tile_label(&self, ctx: &mut Context, tile: (usize, usize), label: &str, bottom_right: bool) {
+        // tiles are light when their row and column add up to an even number
+        let colour = if (tile.0 + tile.1) % 2 == 0 { BLACK } else { WHITE };
+        let text = graphics::Text::new(graphics::TextFragment::new(label)
+            .color(colour)
+            .scale(GRID_CELL_SIZE.1 as f32 / 5.0));
+        let size = text.dimensions(ctx);
+        let x = tile.1 as f32 * GRID_CELL_SIZE.0 as f32;
+        let y = tile.0 as f32 * GRID_CELL_SIZE.1 as f32;
+        let dest = if bottom_right {
+            [x + GRID_CELL_SIZE.0 as f32 - size.w - 2.0, y + GRID_CELL_SIZE.1 as f32 - size.h - 1.0]
+        } else {
+            [x + 2.0, y + 1.0]
+        };
+        graphics::draw(ctx, &text, graphics::DrawParam::default().dest(dest)).expect("Failed to draw text.");
+    }
+
+    /// Draws lines between all tiles and each tile's coordinate in its bottom left corner.
+    fn draw_grid_overlay(&self, ctx: &mut Context) {
+        let size = (GRID_SIZE as f32 * GRID_CELL_SIZE.0 as f32, GRID_SIZE as f32 * GRID_CELL_SIZE.1 as f32);
+        let mut builder = graphics::MeshBuilder::new();
+        for i in 1..GRID_SIZE {
+            let x = i as f32 * GRID_CELL_SIZE.0 as f32;
+            let y = i as f32 * GRID_CELL_SIZE.1 as f32;
+            builder.line(&[[x, 0.0], [x, size.1]], 1.0, self.grid_line_colour).expect("Failed to create grid.");
+            builder.line(&[[0.0, y], [size.0, y]], 1.0, self.grid_line_colour).expect("Failed to create grid.");
+        }
+        let grid = builder.build(ctx).expect("Failed to create grid.");
+        graphics::draw(ctx, &grid, graphics::DrawParam::default()).expect("Failed to draw grid.");
+
+        for (rank, file) in rules::all_squares() {
+            let (x, y) = self.square_to_screen((rank, file));
+            let text = graphics::Text::new(graphics::TextFragment::new(square_to_algebraic((rank, file)))
+                .color(HUD_TEXT)
+                .scale(self.grid_label_size));
+            graphics::draw(ctx, &text, graphics::DrawParam::default()
+                .dest([x + 3.0, y + GRID_CELL_SIZE.1 as f32 - self.grid_label_size - 2.0])
+            ).expect("Failed to draw text.");
+        }
+    }
+
+    fn draw_heatmap(&mut self, ctx: &mut Context) {
+        if self.mobility.is_none() {
+            self.mobility = Some(self.mobility_counts());
+        }
+        let counts = match self.mobility {
+            Some(counts) => counts,
+            None => return,
+        };
+        for (rank, file) in rules::all_squares() {
+            let count = counts[rank][file];
+            if count == 0 {
+                continue;
+            }
+            let (x, y) = self.square_to_screen((rank, file));
+            let strength = count.min(HEATMAP_SATURATION) as f32 / HEATMAP_SATURATION as f32;
+            let rectangle = graphics::Mesh::new_rectangle(ctx,
+                graphics::DrawMode::fill(),
+                graphics::Rect::new(x, y, GRID_CELL_SIZE.0 as f32, GRID_CELL_SIZE.1 as f32),
+                graphics::Color::new(HEATMAP.r, HEATMAP.g, HEATMAP.b, HEATMAP.a * strength)
+                ).expect("Failed to create tile.");
+            graphics::draw(ctx, &rectangle, graphics::DrawParam::default()).expect("Failed to draw tiles.");
+        }
+    }
+
+    /// Overlays shaded onto the tiles below the pieces, hidden in board-only mode.
+    fn draw_tile_overlays(&mut self, ctx: &mut Context) {
+        if let Some((from, to)) = self.last_move {
+            for squ in [from, to].iter() {
+                let (x, y) = self.square_to_screen(*squ);
+                let rectangle = graphics::Mesh::new_rectangle(ctx,
+                    graphics::DrawMode::fill(),
+                    graphics::Rect::new(x, y, GRID_CELL_SIZE.0 as f32, GRID_CELL_SIZE.1 as f32),
+                    LAST_MOVE
+                    ).expect("Failed to create tile.");
+                graphics::draw(ctx, &rectangle, graphics::DrawParam::default()).expect("Failed to draw tiles.");
+            }
+        }
+
+        if self.show_heatmap {
+            self.draw_heatmap(ctx);
+        }
+
+        if let Some(snapshot) = self.snapshot.filter(|_| self.show_snapshot_diff) {
+            self.draw_snapshot_diff(ctx, &snapshot);
+        }
+
+        self.draw_quiz_flash(ctx);
+
+        // orientation guides go below the pieces and the move highlights
+        if let Some(s) = self.ui.selected_square.filter(|_| self.beginner_hints) {
+            self.draw_line_guides(ctx, s);
+        }
+    }
+
+    /// Highlights, markers, panels and the HUD drawn over the pieces, hidden in board-only mode.
+    fn draw_overlays(&mut self, ctx: &mut Context) {
+        if self.beginner_hints {
+            self.draw_promotion_guide(ctx);
+            self.draw_pins(ctx);
+        }
+
+        if let Some(squ) = self.game.en_passant_square {
+            let label = graphics::Text::new(graphics::TextFragment::new("e.p.")
+                .color(EN_PASSANT_LABEL)
+                .scale(GRID_CELL_SIZE.1 as f32 / 4.0));
+            let (x, y) = self.square_to_screen(squ);
+            graphics::draw(ctx, &label, graphics::DrawParam::default().dest([x + 4.0, y + 2.0])).expect("Failed to draw text.");
+        }
+
+        // draw pinned analysis overlays, each piece in its own colour
+        for (i, (squ, moves)) in self.ui.analysis_overlays.iter().enumerate() {
+            let colour = ANALYSIS_COLOURS[i % ANALYSIS_COLOURS.len()];
+            for target in moves.iter() {
+                let (x, y) = self.square_to_screen(*target);
+                let rectangle = graphics::Mesh::new_rectangle(ctx,
+                    graphics::DrawMode::fill(),
+                    graphics::Rect::new(x, y, GRID_CELL_SIZE.0 as f32, GRID_CELL_SIZE.1 as f32),
+                    colour
+                    ).expect("Failed to create tile.");
+                graphics::draw(ctx, &rectangle, graphics::DrawParam::default()).expect("Failed to draw tiles.");
+            }
+            draw_outline(ctx, self.square_to_screen(*squ), 4.0, graphics::Color::new(colour.r, colour.g, colour.b, 1.0));
+        }
+
+        // preview the moves of the hovered piece while nothing is selected
+        if self.ui.selected_square.is_none() {
+            for squ in self.ui.hover_moves.iter() {
+                let (x, y) = self.square_to_screen(*squ);
+                let dot = graphics::Mesh::new_circle(ctx,
+                    graphics::DrawMode::fill(),
+                    [x + GRID_CELL_SIZE.0 as f32 / 2.0, y + GRID_CELL_SIZE.1 as f32 / 2.0],
+                    GRID_CELL_SIZE.0 as f32 / 8.0,
+                    0.5,
+                    HOVER_PREVIEW
+                    ).expect("Failed to create dot.");
+                graphics::draw(ctx, &dot, graphics::DrawParam::default()).expect("Failed to draw dot.");
+            }
+        }
+
+        // draw the previous selection fading out below the current one fading in
+        let tiles = self.ui.fading_tiles.iter().map(|&(squ, colour)| (squ, colour, self.ui.fading_alpha))
+            .chain(self.selection_tiles().into_iter().map(|(squ, colour)| (squ, colour, self.ui.selection_alpha)))
+            .collect::<Vec<((usize,usize), graphics::Color, f32)>>();
+        for (squ, colour, alpha) in tiles {
+            let (x, y) = self.square_to_screen(squ);
+            let rectangle = graphics::Mesh::new_rectangle(ctx, 
+                graphics::DrawMode::fill(), 
+                graphics::Rect::new(x, y, GRID_CELL_SIZE.0 as f32, GRID_CELL_SIZE.1 as f32), 
+                graphics::Color::new(colour.r, colour.g, colour.b, colour.a * alpha)
+                ).expect("Failed to create tile.");
+            graphics::draw(ctx, &rectangle, graphics::DrawParam::default()).expect("Failed to draw tiles.");
+        }
+
+        if let Some(s) = self.ui.selected_square {
+            let is_king = matches!(self.game.board[s.0][s.1], Piece::King(_));
+            if let Some(&squ) = self.ui.focused_destination.and_then(|i| self.ui.highlighted_squares.get(i)) {
+                draw_outline(ctx, self.square_to_screen(squ), 5.0, FOCUSED_DESTINATION);
+            }
+
+            // hovering a castling destination shows where the rook ends up
+            if let Some(to) = self.hover_square.filter(|to| is_king && self.ui.highlighted_squares.contains(to)) {
+                if castling_index(s, to).is_some() {
+                    let rook_file = if to.1 == 6 { 5 } else { 3 };
+                    let rook = get_colour(self.game.board[s.0][s.1]).map(Piece::Rook);
+                    if let Some((rook, image)) = rook.and_then(|r| self.sprites.get(&r).map(|image| (r, image))) {
+                        let param = self.sprite_param(rook, self.square_to_screen((s.0, rook_file)), GRID_CELL_SIZE.0 as f32);
+                        graphics::draw(ctx, image, param.color(CASTLING_PREVIEW)).expect("Failed to draw piece.");
+                    }
+                }
+            }
+
+            // mark the pieces that would be captured, including pawns taken en passant
+            let mover = get_colour(self.game.board[s.0][s.1]);
+            for (to, target) in self.capture_targets(s) {
+                let colour = match mover {
+                    Some(c) if self.capture_safety => {
+                        if rules::capture_is_safe(&self.game.board, s, to, c) { CAPTURE_SAFE } else { CAPTURE_HANGING }
+                    },
+                    _ => CAPTURE_TARGET,
+                };
+                draw_outline(ctx, self.square_to_screen(target), 4.0, colour);
+            }
+
+            // outline the selected piece if it is attacked, and the pieces attacking it
+            if !self.ui.threats.is_empty() {
+                for squ in self.ui.threats.iter() {
+                    draw_outline(ctx, self.square_to_screen(*squ), 3.0, ATTACKER);
+                }
+                draw_outline(ctx, self.square_to_screen(s), 6.0, THREATENED);
+            }
+
+            // cross out a piece of the side to move that can't go anywhere, e.g. because it is pinned
+            let own_piece = get_colour(self.game.board[s.0][s.1]) == Some(self.game.current_turn);
+            if own_piece && self.ui.highlighted_squares.is_empty() && self.draw_claimed.is_none() {
+                draw_cross(ctx, self.square_to_screen(s), NO_MOVES);
+            }
+        }
+
+        if self.show_capture_markers {
+            self.draw_capture_markers(ctx);
+        }
+
+        if self.show_piece_counts {
+            self.draw_piece_counts(ctx);
+        }
+
+        if self.beginner_hints {
+            self.draw_en_passant_toast(ctx);
+        }
+
+        self.draw_hud(ctx);
+
+        if self.debug {
+            self.draw_debug_overlay(ctx);
+        }
+    }
+
+    /// Marks every square captured on this game with a dot in its bottom left corner and the number of captures.
+    fn draw_capture_markers(&self, ctx: &mut Context) {
+        let radius = GRID_CELL_SIZE.0 as f32 / 8.0;
+        for (rank, file) in rules::all_squares() {
+            let count = self.captures[rank][file];
+            if count == 0 {
+                continue;
+            }
+            let (x, y) = self.square_to_screen((rank, file));
+            let centre = [x + radius + 2.0, y + GRID_CELL_SIZE.1 as f32 - radius - 2.0];
+            let marker = graphics::Mesh::new_circle(ctx, graphics::DrawMode::fill(), centre, radius, 0.5, CAPTURE_MARKER)
+                .expect("Failed to create marker.");
+            graphics::draw(ctx, &marker, graphics::DrawParam::default()).expect("Failed to draw marker.");
+
+            let text = graphics::Text::new(graphics::TextFragment::new(count.to_string())
+                .color(HUD_TEXT)
+                .scale(radius * 1.5));
+            let size = text.dimensions(ctx);
+            graphics::draw(ctx, &text, graphics::DrawParam::default()
+                .dest([centre[0] - size.w / 2.0, centre[1] - size.h / 2.0])).expect("Failed to draw text.");
+        }
+    }
+
+    /// Shades every square whose piece differs from the snapshot: added, removed or replaced by another piece.
+    fn draw_snapshot_diff(&self, ctx: &mut Context, snapshot: &rules::Board) {
+        for (rank, file) in rules::all_squares() {
+            let colour = match (snapshot[rank][file], self.game.board[rank][file]) {
+                (before, after) if before == after => continue,
+                (Piece::Empty, _) => DIFF_ADDED,
+                (_, Piece::Empty) => DIFF_REMOVED,
+                _ => DIFF_CHANGED,
+            };
+            let (x, y) = self.square_to_screen((rank, file));
+            let rectangle = graphics::Mesh::new_rectangle(ctx,
+                graphics::DrawMode::fill(),
+                graphics::Rect::new(x, y, GRID_CELL_SIZE.0 as f32, GRID_CELL_SIZE.1 as f32),
+                colour
+                ).expect("Failed to create tile.");
+            graphics::draw(ctx, &rectangle, graphics::DrawParam::default()).expect("Failed to draw tiles.");
+        }
+    }
+
+    /// Draws small sprites with the number of each piece on the board, white above black, in the bottom right corner.
+    fn draw_piece_counts(&self, ctx: &mut Context) {
+        let icon_size = 18.0;
+        let font_size = GRID_CELL_SIZE.1 as f32 / 4.0;
+        let entry_width = 40.0;
+        let row_height = 24.0;
+        let width = PIECE_COUNT_ORDER.len() as f32 * entry_width + 8.0;
+        let height = 2.0 * row_height + 8.0;
+        let left = SCREEN_SIZE.0 - width;
+        let top = GRID_SIZE as f32 * GRID_CELL_SIZE.1 as f32 - height;
+
+        let background = graphics::Mesh::new_rectangle(ctx,
+            graphics::DrawMode::fill(),
+            graphics::Rect::new(left, top, width, height),
+            DEBUG_BACKGROUND
+            ).expect("Failed to create panel.");
+        graphics::draw(ctx, &background, graphics::DrawParam::default()).expect("Failed to draw panel.");
+
+        for (row, colour) in [Colour::White, Colour::Black].iter().enumerate() {
+            for (i, kind) in PIECE_COUNT_ORDER.iter().enumerate() {
+                let piece = kind(*colour);
+                let x = left + 4.0 + i as f32 * entry_width;
+                let y = top + 4.0 + row as f32 * row_height;
+                if let Some(image) = self.sprites.get(&piece) {
+                    graphics::draw(ctx, image, self.sprite_param(piece, (x, y), icon_size)).expect("Failed to draw piece.");
+                }
+                let count = self.piece_counts.get(&piece).copied().unwrap_or(0);
+                let text = graphics::Text::new(graphics::TextFragment::new(count.to_string())
+                    .color(if count > 0 { DEBUG_LIT } else { DEBUG_DIMMED })
+                    .scale(font_size));
+                graphics::draw(ctx, &text, graphics::DrawParam::default().dest([x + 20.0, y])).expect("Failed to draw text.");
+            }
+        }
+    }
+
+    /// Draws the fading "en passant available" hint across the top of the board.
+    fn draw_en_passant_toast(&self, ctx: &mut Context) {
+        let elapsed = match self.en_passant_toast {
+            Some(at) if at.elapsed() < TOAST_DURATION => at.elapsed().as_secs_f32(),
+            _ => return,
+        };
+        // fully visible for the first half, then fading out
+        let fade = (2.0 * (1.0 - elapsed / TOAST_DURATION.as_secs_f32())).min(1.0);
+
+        let text = graphics::Text::new(graphics::TextFragment::new("En passant available")
+            .color(graphics::Color::new(HUD_TEXT.r, HUD_TEXT.g, HUD_TEXT.b, HUD_TEXT.a * fade))
+            .scale(HUD_HEIGHT * 0.6));
+        let size = text.dimensions(ctx);
+        let x = (SCREEN_SIZE.0 - size.w) / 2.0;
+        let y = GRID_CELL_SIZE.1 as f32 / 4.0;
+        let background = graphics::Mesh::new_rectangle(ctx,
+            graphics::DrawMode::fill(),
+            graphics::Rect::new(x - 10.0, y - 6.0, size.w + 20.0, size.h + 12.0),
+            graphics::Color::new(TOAST_BACKGROUND.r, TOAST_BACKGROUND.g, TOAST_BACKGROUND.b, TOAST_BACKGROUND.a * fade)
+            ).expect("Failed to create toast.");
+        graphics::draw(ctx, &background, graphics::DrawParam::default()).expect("Failed to draw toast.");
+        graphics::draw(ctx, &text, graphics::DrawParam::default().dest([x, y])).expect("Failed to draw text.");
+    }
+
+    /// Pauses or unpauses, along with the quiz timer. Not available during playback,
+    /// which has to keep to the recorded timing.
+    fn toggle_pause(&mut self) {
+        if self.playback.is_some() {
+            return;
+        }
+        self.paused = !self.paused;
+        if let Some(quiz) = self.quiz.as_mut() {
+            if self.paused { quiz.pause() } else { quiz.resume() }
+        }
+    }
+
+    /// Dims everything and says so in the middle of the board.
+    fn draw_pause_overlay(&self, ctx: &mut Context) {
+        let dim = graphics::Mesh::new_rectangle(ctx,
+            graphics::DrawMode::fill(),
+            graphics::Rect::new(0.0, 0.0, SCREEN_SIZE.0, SCREEN_SIZE.1),
+            PAUSE_DIM
+            ).expect("Failed to create overlay.");
+        graphics::draw(ctx, &dim, graphics::DrawParam::default()).expect("Failed to draw overlay.");
+
+        let text = graphics::Text::new(graphics::TextFragment::new("Paused")
+            .color(HUD_TEXT)
+            .scale(GRID_CELL_SIZE.1 as f32));
+        let size = text.dimensions(ctx);
+        let board_size = GRID_SIZE as f32 * GRID_CELL_SIZE.1 as f32;
+        graphics::draw(ctx, &text, graphics::DrawParam::default()
+            .dest([(SCREEN_SIZE.0 - size.w) / 2.0, (board_size - size.h) / 2.0])
+        ).expect("Failed to draw text.");
+    }
+
+    /// Flashes the last quiz answer green or red.
+    fn draw_quiz_flash(&self, ctx: &mut Context) {
+        if let Some((squ, right, at)) = self.quiz.as_ref().and_then(|q| q.flash) {
+            if at.elapsed() < quiz::FLASH_DURATION {
+                let (x, y) = self.square_to_screen(squ);
+                let rectangle = graphics::Mesh::new_rectangle(ctx,
+                    graphics::DrawMode::fill(),
+                    graphics::Rect::new(x, y, GRID_CELL_SIZE.0 as f32, GRID_CELL_SIZE.1 as f32),
+                    if right { QUIZ_RIGHT } else { QUIZ_WRONG }
+                    ).expect("Failed to create tile.");
+                graphics::draw(ctx, &rectangle, graphics::DrawParam::default()).expect("Failed to draw tiles.");
+            }
+        }
+    }
+
+    /// Faintly shades the rank and file of the selected piece, and for bishops and queens its diagonals too.
+    fn draw_line_guides(&self, ctx: &mut Context, from: (usize, usize)) {
+        let diagonals = matches!(self.game.board[from.0][from.1], Piece::Bishop(_) | Piece::Queen(_));
+        let mut builder = graphics::MeshBuilder::new();
+        for (rank, file) in rules::all_squares() {
+            let straight = rank == from.0 || file == from.1;
+            let diagonal = diagonals && (rank as i32 - from.0 as i32).abs() == (file as i32 - from.1 as i32).abs();
+            if (rank, file) == from || !(straight || diagonal) {
+                continue;
+            }
+            let (x, y) = self.square_to_screen((rank, file));
+            builder.rectangle(graphics::DrawMode::fill(),
+                graphics::Rect::new(x, y, GRID_CELL_SIZE.0 as f32, GRID_CELL_SIZE.1 as f32),
+                LINE_GUIDE
+                ).expect("Failed to create guide.");
+        }
+        let guides = builder.build(ctx).expect("Failed to create guide.");
+        graphics::draw(ctx, &guides, graphics::DrawParam::default()).expect("Failed to draw guide.");
+    }
+
+    /// Draws a small pin in the top right corner of every pinned piece of the side to move.
+    fn draw_pins(&self, ctx: &mut Context) {
+        for squ in rules::pinned_pieces(&self.game.board, self.game.current_turn) {
+            let (x, y) = self.square_to_screen(squ);
+            let head = [x + GRID_CELL_SIZE.0 as f32 * 0.82, y + GRID_CELL_SIZE.1 as f32 * 0.14];
+            let point = [x + GRID_CELL_SIZE.0 as f32 * 0.68, y + GRID_CELL_SIZE.1 as f32 * 0.3];
+
+            let mut builder = graphics::MeshBuilder::new();
+            builder.line(&[head, point], 3.0, PIN_ICON).expect("Failed to create pin.");
+            builder.circle(graphics::DrawMode::fill(), head, GRID_CELL_SIZE.0 as f32 / 15.0, 0.5, PIN_ICON).expect("Failed to create pin.");
+            let pin = builder.build(ctx).expect("Failed to create pin.");
+            graphics::draw(ctx, &pin, graphics::DrawParam::default()).expect("Failed to draw pin.");
+        }
+    }
+
+    /// Text for the HUD: the move being built, or the one just completed.
+    fn hud_text(&self) -> String {
+        if let Some((notice, _)) = self.notice.as_ref().filter(|(_, at)| at.elapsed() < NOTICE_DURATION) {
+            notice.clone()
+        } else if let Some(quiz) = self.quiz.as_ref() {
+            if quiz.is_over() {
+                format!("Time! {} found, {} missed, best {}   Q to stop",
+                    quiz.correct, quiz.incorrect, self.stats.quiz_best)
+            } else {
+                format!("Find {}   {} found, {} missed   {}s", square_to_algebraic(quiz.target),
+                    quiz.correct, quiz.incorrect, quiz.time_left().as_secs() + 1)
+            }
+        } else if self.analysis {
+            "Analysis: click pieces to compare their moves".to_string()
+        } else if let Some(from) = self.ui.selected_square {
+            match self.hover_square {
+                Some(to) if self.ui.highlighted_squares.contains(&to) =>
+                    format!("{} \u{2192} {}", square_to_algebraic(from), square_to_algebraic(to)),
+                _ => square_to_algebraic(from),
+            }
+        } else if let Some(kind) = self.terminal_state() {
+            match kind {
+                TerminalKind::Checkmate(Colour::White) => "Checkmate, white wins".to_string(),
+                TerminalKind::Checkmate(Colour::Black) => "Checkmate, black wins".to_string(),
+                TerminalKind::Stalemate => "Stalemate".to_string(),
+                TerminalKind::DeadPosition => "Draw, dead position".to_string(),
+                TerminalKind::FiftyMoveRule => "Draw, 50-move rule".to_string(),
+                TerminalKind::Repetition => "Draw, threefold repetition".to_string(),
+                TerminalKind::NoMovesLeft(Colour::White) => "Out of moves, white wins".to_string(),
+                TerminalKind::NoMovesLeft(Colour::Black) => "Out of moves, black wins".to_string(),
+            }
+        } else if let Some(kind) = self.claimable_draw() {
+            match kind {
+                TerminalKind::Repetition => "Threefold repetition, press C to claim a draw",
+                TerminalKind::FiftyMoveRule => "50 moves without a capture or pawn move, press C to claim a draw",
+                _ => "Dead position, press C to claim a draw",
+            }.to_string()
+        } else if let Some(warning) = self.draw_warning() {
+            warning
+        } else if self.moves_played == 0 && self.stats.games_finished > 0 {
+            format!("{} games: white {}, black {}, draws {}, {} moves on average",
+                self.stats.games_finished, self.stats.white_wins, self.stats.black_wins, self.stats.draws, self.stats.average_moves())
+        } else if let Some(index) = self.opening.filter(|&i| self.moves_played as usize == OPENINGS[i].1.len()) {
+            OPENINGS[index].0.to_string()
+        } else if let Some((from, to, _)) = self.completed_move.filter(|&(_, _, at)| at.elapsed() < HUD_MOVE_DURATION) {
+            format!("{} \u{2192} {}   {}", square_to_algebraic(from), square_to_algebraic(to), self.move_history.last().map_or("", |m| m.as_str()))
+        } else {
+            self.restriction_note()
+        }
+    }
+
+    /// Reminder of the teaching restrictions in force, so a missing castling or en passant doesn't look like a bug.
+    fn restriction_note(&self) -> String {
+        match (self.allow_castling, self.allow_en_passant) {
+            (true, true) => String::new(),
+            (false, true) => "Teaching rules: no castling".to_string(),
+            (true, false) => "Teaching rules: no en passant".to_string(),
+            (false, false) => "Teaching rules: no castling or en passant".to_string(),
+        }
+    }
+
+    /// Draws the status strip below the board.
+    fn draw_hud(&self, ctx: &mut Context) {
+        let top = GRID_SIZE as f32 * GRID_CELL_SIZE.1 as f32;
+        let background = graphics::Mesh::new_rectangle(ctx,
+            graphics::DrawMode::fill(),
+            graphics::Rect::new(0.0, top, SCREEN_SIZE.0, HUD_HEIGHT),
+            HUD_BACKGROUND
+            ).expect("Failed to create HUD.");
+        graphics::draw(ctx, &background, graphics::DrawParam::default()).expect("Failed to draw HUD.");
+
+        let text = match (self.free_play.as_ref(), self.hud_text()) {
+            (Some(_), text) if text.is_empty() => "Free play, E to return to the game".to_string(),
+            (Some(_), text) => format!("Free play | {}", text),
+            (None, text) => text,
+        };
+        let text = graphics::Text::new(graphics::TextFragment::new(text)
+            .color(HUD_TEXT)
+            .scale(HUD_HEIGHT * 0.6));
+        graphics::draw(ctx, &text, graphics::DrawParam::default().dest([10.0, top + HUD_HEIGHT * 0.2])).expect("Failed to draw text.");
+    }
+
+    /// Draws castling rights and the en passant target square, for diagnosing move generation.
+    fn draw_debug_overlay(&self, ctx: &mut Context) {
+        if let Some(s) = self.game.en_passant_square {
+            let (x, y) = self.square_to_screen(s);
+            let marker = graphics::Mesh::new_circle(ctx,
+                graphics::DrawMode::stroke(4.0),
+                [x + GRID_CELL_SIZE.0 as f32 / 2.0, y + GRID_CELL_SIZE.1 as f32 / 2.0],
+                GRID_CELL_SIZE.0 as f32 / 4.0,
+                0.5,
+                DEBUG_MARKER
+                ).expect("Failed to create marker.");
+            graphics::draw(ctx, &marker, graphics::DrawParam::default()).expect("Failed to draw marker.");
+        }
+
+        // castling rights as "KQkq" with lost rights dimmed, then the latest move strings
+        let font_size = GRID_CELL_SIZE.1 as f32 / 4.0;
+        let mut text = graphics::Text::new("");
+        for (symbol, available) in CASTLING_SYMBOLS.iter().zip(self.castling_rights.iter()) {
+            text.add(graphics::TextFragment::new(*symbol)
+                .color(if *available { DEBUG_LIT } else { DEBUG_DIMMED })
+                .scale(font_size));
+        }
+        for (string, accepted) in self.move_log.iter() {
+            text.add(graphics::TextFragment::new(format!("\n\"{}\" {}", string, if *accepted { "ok" } else { "rejected" }))
+                .color(if *accepted { DEBUG_LIT } else { DEBUG_MARKER })
+                .scale(font_size));
+        }
+
+        let background = graphics::Mesh::new_rectangle(ctx,
+            graphics::DrawMode::fill(),
+            graphics::Rect::new(0.0, 0.0, 3.0 * GRID_CELL_SIZE.0 as f32, (1 + self.move_log.len()) as f32 * font_size * 1.25 + 8.0),
+            DEBUG_BACKGROUND
+            ).expect("Failed to create tile.");
+        graphics::draw(ctx, &background, graphics::DrawParam::default()).expect("Failed to draw tiles.");
+        graphics::draw(ctx, &text, graphics::DrawParam::default().dest([4.0, 4.0])).expect("Failed to draw text.");
+
+        self.draw_frame_graph(ctx);
+    }
+
+    /// Draws the latest frame durations as bars in the top right corner, with the current FPS above them.
+    fn draw_frame_graph(&self, ctx: &mut Context) {
+        let bar_width = 2.0;
+        let width = FRAME_GRAPH_LENGTH as f32 * bar_width;
+        let height = GRID_CELL_SIZE.1 as f32 * 0.75;
+        let font_size = GRID_CELL_SIZE.1 as f32 / 4.0;
+        let left = SCREEN_SIZE.0 - width - 4.0;
+        let bottom = 4.0 + font_size * 1.25 + height;
+
+        // one mesh for the background and every bar keeps this to a single draw call
+        let mut builder = graphics::MeshBuilder::new();
+        builder.rectangle(graphics::DrawMode::fill(),
+            graphics::Rect::new(left - 4.0, 0.0, width + 8.0, bottom + 4.0),
+            DEBUG_BACKGROUND
+            ).expect("Failed to create graph.");
+        for (i, duration) in self.frame_times.iter().enumerate() {
+            let bar_height = (duration / FRAME_GRAPH_SCALE).min(1.0) * height;
+            builder.rectangle(graphics::DrawMode::fill(),
+                graphics::Rect::new(left + i as f32 * bar_width, bottom - bar_height, bar_width, bar_height),
+                FRAME_GRAPH_BAR
+                ).expect("Failed to create graph.");
+        }
+        let graph = builder.build(ctx).expect("Failed to create graph.");
+        graphics::draw(ctx, &graph, graphics::DrawParam::default()).expect("Failed to draw graph.");
+
+        let text = graphics::Text::new(graphics::TextFragment::new(format!("{:.0} FPS", ggez::timer::fps(ctx)))
+            .color(DEBUG_LIT)
+            .scale(font_size));
+        graphics::draw(ctx, &text, graphics::DrawParam::default().dest([left, 4.0])).expect("Failed to draw text.");
+    }
+}
+
+impl event::EventHandler<GameError> for AppState {
+
+    /// For updating game logic, which front-end doesn't handle.
+    fn update(&mut self, ctx: &mut Context) -> GameResult {
+        if self.frame_times.len() == FRAME_GRAPH_LENGTH {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(ggez::timer::delta(ctx).as_secs_f32());
+
+        let status = self.status_text();
+        if status != self.title_status {
+            graphics::set_window_title(ctx, &format!("{} - {}", self.title, status));
+            self.title_status = status.to_string();
+        }
+
+        // nothing moves while paused, and the time paused is simply dropped
+        if self.paused {
+            return Ok(());
+        }
+
+        let fade = ggez::timer::delta(ctx).as_secs_f32() / HIGHLIGHT_FADE;
+        self.ui.selection_alpha = (self.ui.selection_alpha + fade).min(1.0);
+        self.ui.fading_alpha = (self.ui.fading_alpha - fade).max(0.0);
+        if self.ui.fading_alpha == 0.0 {
+            self.ui.fading_tiles.clear();
+        }
+
+        let lift_target = if self.ui.lift_square.is_some() && self.ui.lift_square == self.ui.selected_square { LIFT_HEIGHT } else { 0.0 };
+        let lift_step = ggez::timer::delta(ctx).as_secs_f32() * LIFT_HEIGHT / LIFT_DURATION;
+        self.ui.lift_offset = if self.ui.lift_offset < lift_target {
+            (self.ui.lift_offset + lift_step).min(lift_target)
+        } else {
+            (self.ui.lift_offset - lift_step).max(lift_target)
+        };
+
+        // the bottom rank has the furthest to fall, so it sets the speed
+        let fall = ggez::timer::delta(ctx).as_secs_f32() * GRID_SIZE as f32 * GRID_CELL_SIZE.1 as f32 / INTRO_DURATION;
+        for offset in self.ui.drop_offsets.iter_mut().flat_map(|offsets| offsets.iter_mut()) {
+            *offset = (*offset - fall).max(0.0);
+        }
+
+        // count a finished quiz round once
+        if let Some(quiz) = self.quiz.as_mut() {
+            if quiz.is_over() && !quiz.scored {
+                quiz.scored = true;
+                if quiz.correct > self.stats.quiz_best {
+                    self.stats.quiz_best = quiz.correct;
+                    self.stats.save(&self.resource_dir);
+                }
+            }
+        }
+
+        let due = match self.playback.as_mut() {
+            Some(playback) => playback.due_events(),
+            None => Vec::new(),
+        };
+        for event in due {
+            self.replay_event(ctx, event);
+        }
+        if self.playback.as_ref().is_some_and(|p| p.is_finished()) {
+            self.notice = Some(("Playback finished".to_string(), time::Instant::now()));
+            self.playback = None;
+        }
+
+        Ok(())
+    }
+
+    /// Draw interface, i.e. draw game board
+    fn draw(&mut self, ctx: &mut Context) -> GameResult {
+
+        // clear to the letterbox colour, which shows in the bars around the board
+        graphics::clear(ctx, LETTERBOX);
+        let background = graphics::Mesh::new_rectangle(ctx,
+            graphics::DrawMode::fill(),
+            graphics::Rect::new(0.0, 0.0, SCREEN_SIZE.0, SCREEN_SIZE.1),
+            [0.5, 0.5, 0.5, 1.0].into()
+            ).expect("Failed to create background.");
+        graphics::draw(ctx, &background, graphics::DrawParam::default()).expect("Failed to draw background.");
+
+
+        // draw grid, cached as a single mesh
+        if self.board_mesh.is_none() {
+            let lines = if self.tile_lines { Some(self.tile_line_colour) } else { None };
+            self.board_mesh = Some(AppState::build_board_mesh(ctx, lines)?);
+        }
+        if let Some(mesh) = self.board_mesh.as_ref() {
+            graphics::draw(ctx, mesh, graphics::DrawParam::default()).expect("Failed to draw tiles.");
+        }
+
+        // the grid overlay labels every tile already
+        if self.coordinate_labels && !self.grid_overlay {
+            self.draw_coordinate_labels(ctx);
+        }
+
+        if !self.board_only {
+            self.draw_tile_overlays(ctx);
+        }
+
+        // draw pieces, batched per sprite, unless the quiz hides them
+        let hide_pieces = self.quiz.as_ref().is_some_and(|q| q.hide_pieces);
+        let lifted = self.ui.lift_square
+            .filter(|squ| self.ui.lift_offset > 0.0 && !hide_pieces && self.game.board[squ.0][squ.1] != Piece::Empty);
+        if let Some(squ) = lifted {
+            let (x, y) = self.square_to_screen(squ);
+            let shadow = graphics::Mesh::new_circle(ctx,
+                graphics::DrawMode::fill(),
+                [x + GRID_CELL_SIZE.0 as f32 / 2.0, y + GRID_CELL_SIZE.1 as f32 * 0.8],
+                GRID_CELL_SIZE.0 as f32 / 4.0,
+                0.5,
+                graphics::Color::new(LIFT_SHADOW.r, LIFT_SHADOW.g, LIFT_SHADOW.b, LIFT_SHADOW.a * self.ui.lift_offset / LIFT_HEIGHT)
+                ).expect("Failed to create shadow.");
+            graphics::draw(ctx, &shadow, graphics::DrawParam::default()).expect("Failed to draw shadow.");
+        }
+        if !hide_pieces {
+            for _row in 0..8 {
+                for _col in 0..8 {
+                    let piece = self.game.board[_row][_col];
+                    let (x, y) = self.square_to_screen((_row, _col));
+                    let lift = if self.ui.lift_square == Some((_row, _col)) { self.ui.lift_offset } else { 0.0 };
+                    let param = self.sprite_param(piece, (x, y - self.ui.drop_offsets[_row][_col] - lift), GRID_CELL_SIZE.0 as f32);
+                    if let Some(batch) = self.piece_batches.get_mut(&piece) {
+                        batch.add(param);
+                    }
+                }
+            }
+        }
+        for batch in self.piece_batches.values_mut() {
+            graphics::draw(ctx, batch, graphics::DrawParam::default()).expect("Failed to draw piece.");
+            batch.clear();
+        }
+
+        if !self.board_only {
+            self.draw_overlays(ctx);
+        }
+
+        if let Some(to) = self.ui.promotion_pending {
+            self.draw_promotion_dialog(ctx, to);
+        }
+
+        if self.grid_overlay {
+            self.draw_grid_overlay(ctx);
+        }
+
+        if self.paused {
+            self.draw_pause_overlay(ctx);
+        }
+
+        // render updated graphics
+        graphics::present(ctx).expect("Failed to update graphics.");
+
+        Ok(())
+    }
+
+    /// Update game on mouse click
+    fn mouse_button_up_event(&mut self, ctx: &mut Context, button: event::MouseButton, x: f32, y: f32) {
+        self.record(recording::InputEvent::MouseUp { button: recording::button_name(button), x, y });
+        let (x, y) = self.window_to_board(x, y);
+        if button == event::MouseButton::Left && !self.paused {
+            /* check click position and update board accordingly */
+            let (rank, file) = match self.screen_to_square(x, y) {
+                Some(square) => square,
+                None => return,
+            };
+            if let Some(quiz) = self.quiz.as_mut() {
+                quiz.answer((rank, file));
+                return;
+            }
+            if self.analysis {
+                self.toggle_analysis_overlay((rank, file));
+                return;
+            }
+            if let Some(to) = self.ui.promotion_pending {
+                self.answer_promotion(to, (rank, file));
+                return;
+            }
+            match self.ui.selected_square {
+                Some(pos) => {
+                    if pos == (rank, file) {
+                        self.clear_selection();
+                    } else if self.ui.highlighted_squares.iter().any(|p| p == &(rank,file)) {
+                        self.play_or_promote(pos, (rank, file));
+                    } else {
+                        self.select_square((rank, file));
+                    }
+                },
+                None => self.select_square((rank, file))
+            }
+            self.ui.cycle_index = None;
+        }
+    }
+
+    /// Keep the board square and centred in the resized window.
+    fn resize_event(&mut self, ctx: &mut Context, width: f32, height: f32) {
+        self.fit_view(ctx, width, height);
+    }
+
+    /// Closing the window through the title bar cleans up like quitting with Escape.
+    fn quit_event(&mut self, _ctx: &mut Context) -> bool {
+        self.shutdown();
+        false
+    }
+
+    /// Track the hovered square for the HUD
+    fn mouse_motion_event(&mut self, _ctx: &mut Context, x: f32, y: f32, _dx: f32, _dy: f32) {
+        self.record(recording::InputEvent::MouseMotion { x, y });
+        if self.paused {
+            return;
+        }
+        let (x, y) = self.window_to_board(x, y);
+        self.hover_point = Some((x, y));
+        let hover = self.screen_to_square(x, y);
+        if hover != self.hover_square {
+            self.hover_square = hover;
+            self.update_hover_moves();
+        }
+    }
+
+    /// Scroll through the pieces of the open promotion dialog, to be picked with a click or Return
+    fn mouse_wheel_event(&mut self, _ctx: &mut Context, _x: f32, y: f32) {
+        self.record(recording::InputEvent::MouseWheel { y });
+        if !self.paused {
+            self.scroll_promotion(y);
+        }
+    }
+
+    fn key_down_event(
+        &mut self,
+        ctx: &mut Context,
+        keycode: event::KeyCode,
+        keymods: event::KeyMods,
+        _repeat: bool,
+    ) {
+        if let Some(name) = keys::key_name(keycode) {
+            self.record(recording::InputEvent::KeyDown { key: name.to_string(), shift: keymods.contains(event::KeyMods::SHIFT) });
+        }
+        let action = self.key_bindings.action(keycode);
+        if self.paused && action != Some(keys::Action::Pause) {
+            return;
+        }
+        match action {
+            Some(keys::Action::Pause) => self.toggle_pause(),
+            Some(keys::Action::Quit) => {
+                // first press drops the current selection or promotion, second press quits
+                if self.ui.selected_square.is_some() || self.ui.cycle_index.is_some() {
+                    self.clear_selection();
+                    self.ui.cycle_index = None;
+                } else {
+                    self.shutdown();
+                    event::quit(ctx);
+                }
+            },
+            Some(keys::Action::Reset) => self.new_game(),
+            Some(keys::Action::ClaimDraw) if self.review.is_none() && self.claimable_draw().is_some() => {
+                self.draw_claimed = self.claimable_draw();
+                self.mobility = None;
+                self.clear_selection();
+                self.check_game_over();
+            },
+            Some(keys::Action::FreePlay) => self.toggle_free_play(),
+            Some(keys::Action::Analysis) => {
+                self.analysis = !self.analysis;
+                self.ui.analysis_overlays.clear();
+                self.clear_selection();
+                self.ui.cycle_index = None;
+            },
+            Some(keys::Action::Debug) => self.debug = !self.debug,
+            Some(keys::Action::BeginnerHints) => {
+                self.beginner_hints = !self.beginner_hints;
+                config::AppConfig::save_setting(&self.resource_dir, "beginner_hints", self.beginner_hints.into());
+            },
+            Some(keys::Action::SpriteFilter) => self.toggle_sprite_filter(),
+            Some(keys::Action::CycleSelection) => self.cycle_selection(keymods.contains(event::KeyMods::SHIFT)),
+            Some(keys::Action::ConfirmMove) => {
+                // with the promotion dialog open, Enter picks the focused piece as a click on it would
+                if let Some(to) = self.ui.promotion_pending {
+                    let (squ, _) = self.promotion_squares(to)[self.ui.promotion_focus];
+                    self.answer_promotion(to, squ);
+                } else {
+                    let index = self.ui.focused_destination.unwrap_or(0);
+                    if let (Some(pos), Some(&to)) = (self.ui.selected_square, self.ui.highlighted_squares.get(index)) {
+                        self.play_or_promote(pos, to);
+                    }
+                }
+            },
+            Some(keys::Action::NextDestination) => self.cycle_destination(false),
+            Some(keys::Action::PreviousDestination) => self.cycle_destination(true),
+            Some(keys::Action::Opening) => self.load_opening(self.opening.map_or(0, |i| (i + 1) % OPENINGS.len())),
+            Some(keys::Action::Snapshot) => {
+                self.snapshot = Some(self.game.board);
+                self.show_snapshot_diff = true;
+            },
+            Some(keys::Action::SnapshotDiff) => self.show_snapshot_diff = !self.show_snapshot_diff,
+            Some(keys::Action::ClearSnapshot) => self.snapshot = None,
+            Some(keys::Action::Quiz) => {
+                self.quiz = match self.quiz {
+                    Some(_) => None,
+                    None => Some(quiz::Quiz::new(false)),
+                };
+                self.clear_selection();
+                self.ui.cycle_index = None;
+            },
+            Some(keys::Action::QuizPieces) => {
+                if let Some(quiz) = self.quiz.as_mut() {
+                    quiz.hide_pieces = !quiz.hide_pieces;
+                }
+            },
+            Some(keys::Action::CaptureSafety) => {
+                self.capture_safety = !self.capture_safety;
+                config::AppConfig::save_setting(&self.resource_dir, "capture_safety", self.capture_safety.into());
+            },
+            Some(keys::Action::TileLines) => self.toggle_tile_lines(),
+            Some(keys::Action::GridOverlay) => {
+                self.grid_overlay = !self.grid_overlay;
+                config::AppConfig::save_setting(&self.resource_dir, "grid_overlay", self.grid_overlay.into());
+            },
+            Some(keys::Action::Heatmap) => self.show_heatmap = !self.show_heatmap,
+            Some(keys::Action::CaptureMarkers) => self.show_capture_markers = !self.show_capture_markers,
+            Some(keys::Action::FlipBoard) => {
+                self.flipped = !self.flipped;
+                self.refresh_hover_square();
+                self.update_hover_moves();
+            },
+            Some(keys::Action::ReviewBack) => self.step_review(true),
+            Some(keys::Action::ReviewForward) => self.step_review(false),
+            Some(keys::Action::Mute) => self.toggle_sound(),
+            Some(keys::Action::VolumeDown) => self.step_volume(false),
+            Some(keys::Action::VolumeUp) => self.step_volume(true),
+            Some(keys::Action::BoardOnly) => {
+                self.board_only = !self.board_only;
+                config::AppConfig::save_setting(&self.resource_dir, "board_only", self.board_only.into());
+            },
+            Some(keys::Action::Fullscreen) => self.toggle_fullscreen(ctx),
+            Some(keys::Action::PieceCounts) => {
+                self.show_piece_counts = !self.show_piece_counts;
+                config::AppConfig::save_setting(&self.resource_dir, "piece_counts", self.show_piece_counts.into());
+            },
+            Some(keys::Action::HoverPreview) => {
+                self.hover_preview = !self.hover_preview;
+                self.update_hover_moves();
+                config::AppConfig::save_setting(&self.resource_dir, "hover_preview", self.hover_preview.into());
+            },
+            Some(keys::Action::AutoPromote) => self.cycle_auto_promote(),
+            Some(keys::Action::PasteFen) => self.paste_fen(),
+            Some(keys::Action::CopyBoard) => {
+                let diagram = notation::board_ascii(&self.game.board, self.game.current_turn, self.flipped);
+                print!("{}", diagram);
+                copy_to_clipboard(diagram);
+            },
+            Some(keys::Action::CopyMoves) => {
+                // the side to move in the first position moved first, whichever position is shown
+                let first = self.positions[0].1;
+                let moves = notation::compact_moves(&self.move_history, first == Colour::Black, self.result_token());
+                copy_to_clipboard(moves);
+                self.notice = Some(("Moves copied to the clipboard".to_string(), time::Instant::now()));
+            },
+            // a draw that can't be claimed yet
+            Some(keys::Action::ClaimDraw) | None => {}
+        }
+    }
+}
+
+pub fn main() -> GameResult {
+
+    let cli = CliArgs::parse();
+
+    if let Some(games) = cli.fuzz {
+        std::process::exit(if fuzz::run(games) { 0 } else { 1 });
+    }
+
+    let resource_dir = path::PathBuf::from("./resources");
+    check_resources(&resource_dir);
+    let config = config::AppConfig::load(&resource_dir);
+
+    let mut window_setup = conf::WindowSetup::default()
+        .title(&config.title);                  // Set window title, "Schack" by default
+    if let Some(icon) = config.icon_path() {
+        window_setup = window_setup.icon(&icon); // Set application icon, if there is a usable one
+    }
+
+    let context_builder = ContextBuilder::new("schack", "viola")
+        .add_resource_path(resource_dir.clone()) // Import image files to GGEZ
+        .window_setup(window_setup)
+        .window_mode(
+            conf::WindowMode::default()
+                .dimensions(SCREEN_SIZE.0, SCREEN_SIZE.1) // Set window dimensions
+                .resizable(true)                // The board is letterboxed to keep its shape
+        );
+    let (mut contex, mut event_loop) = context_builder.build().expect("Failed to build context.");
+
+    let mut state = match AppState::new(&mut contex, &config, resource_dir) {
+        Ok(s) => s,
+        Err(e) => {
+            eprintln!("Error: could not load the game resources: {}", e);
+            std::process::exit(1);
+        }
+    };
+    if let Some(fen) = cli.fen {
+        if let Err(e) = state.load_fen(&fen) {
+            eprintln!("Error: could not load FEN ({}), starting from the standard position", e);
+        }
+    }
+    if let Some(file) = cli.record {
+        match recording::Recorder::create(&file) {
+            Ok(r) => state.recorder = Some(r),
+            Err(e) => eprintln!("Warning: could not create recording {} ({})", file.display(), e),
+        }
+    }
+    if let Some(file) = cli.replay {
+        match recording::Playback::load(&file) {
+            Ok(p) => state.playback = Some(p),
+            Err(e) => eprintln!("Warning: could not read recording {} ({})", file.display(), e),
+        }
+    }
+    event::run(contex, event_loop, state)       // Run window event loop
+}
+
+/// Exits with a readable message if the resource directory or any sprite is missing.
+fn check_resources(resource_dir: &path::Path) {
+    let missing = missing_sprites(resource_dir);
+
+    if missing.is_empty() {
+        return;
+    }
+
+    if !resource_dir.is_dir() {
+        eprintln!("Error: resource directory {} not found.", resource_dir.display());
+    } else {
+        eprintln!("Error: missing files in resource directory {}:", resource_dir.display());
+        for _path in missing.iter() {
+            eprintln!("    {}", _path.display());
+        }
+    }
+    if let Ok(cwd) = env::current_dir() {
+        eprintln!("Expected the resources relative to {}.", cwd.display());
+    }
+    eprintln!("Run the game from the repository root so that ./resources contains the piece sprites.");
+    std::process::exit(1);
+}
+
+/// Origin and scale of the view, as in `AppState::view_origin` and `view_scale`, that fit the board and HUD into a
+/// window of the given size, centred with bars on the sides that are too long. None for a window with no area.
+fn letterbox(width: f32, height: f32) -> Option<((f32, f32), f32)> {
+    if width <= 0.0 || height <= 0.0 {
+        return None;
+    }
+    let scale = (width / SCREEN_SIZE.0).min(height / SCREEN_SIZE.1);
+    let visible = (width / scale, height / scale);
+    Some((((visible.0 - SCREEN_SIZE.0) / 2.0, (visible.1 - SCREEN_SIZE.1) / 2.0), scale))
+}
+
+/// Converts window coordinates to board coordinates for a view with its board `origin` offset from the window's
+/// top left corner, in board units, and `scale` window pixels per board unit.
+fn window_to_board(x: f32, y: f32, origin: (f32, f32), scale: f32) -> (f32, f32) {
+    (x / scale - origin.0, y / scale - origin.1)
+}
+
+/// Square under a point in screen coordinates, if it is on the board.
+fn screen_to_square(x: f32, y: f32, flipped: bool) -> Option<(usize, usize)> {
+    let row = (y / GRID_CELL_SIZE.1 as f32).floor();
+    let col = (x / GRID_CELL_SIZE.0 as f32).floor();
+    if (0.0..GRID_SIZE as f32).contains(&row) && (0.0..GRID_SIZE as f32).contains(&col) {
+        Some(flip((row as usize, col as usize), flipped))
+    } else {
+        None
+    }
+}
+
+/// Top left corner of a square's tile in screen coordinates.
+fn square_to_screen(square: (usize, usize), flipped: bool) -> (f32, f32) {
+    let (row, col) = flip(square, flipped);
+    (col as f32 * GRID_CELL_SIZE.0 as f32, row as f32 * GRID_CELL_SIZE.1 as f32)
+}
+
+/// Turns a square around the centre of the board when it is flipped, going either way between squares
+/// and the tiles showing them.
+fn flip(square: (usize, usize), flipped: bool) -> (usize, usize) {
+    if flipped {
+        (GRID_SIZE as usize - 1 - square.0, GRID_SIZE as usize - 1 - square.1)
+    } else {
+        square
+    }
+}
+
+/// File letter along the bottom of screen column `column`. Text is always drawn upright, so on a flipped board
+/// only the letters turn with the squares, running h to a and still reading from the side at the bottom.
+fn file_label(column: usize, flipped: bool) -> char {
+    (b'a' + flip((0, column), flipped).1 as u8) as char
+}
+
+/// Rank number beside screen row `row`, turned with the squares like `file_label`.
+fn rank_label(row: usize, flipped: bool) -> char {
+    (b'8' - flip((row, 0), flipped).0 as u8) as char
+}
+
+/// Paths of the piece sprites that are not in `resource_dir`.
+fn missing_sprites(resource_dir: &path::Path) -> Vec<path::PathBuf> {
+    SPRITE_FILES
+        .iter()
+        .map(|(_, _path)| resource_dir.join(_path.trim_start_matches('/')))
+        .filter(|_path| !_path.is_file())
+        .collect()
+}
+
+/// Fails with the first piece sprite missing from `resource_dir`, if any is.
+fn check_sprites(resource_dir: &path::Path) -> GameResult {
+    match missing_sprites(resource_dir).first() {
+        Some(missing) => Err(GameError::ResourceNotFound(missing.display().to_string(), Vec::new())),
+        None => Ok(()),
+    }
+}
+
+/// Draw that threefold repetition or the 50-move rule lets the players claim, after the current position has come up
+/// `repeats` times and `halfmove_clock` half-moves have gone by without a capture or pawn move.
+fn rule_draw(repeats: u32, halfmove_clock: u32) -> Option<TerminalKind> {
+    if repeats >= 3 {
+        Some(TerminalKind::Repetition)
+    } else if halfmove_clock >= FIFTY_MOVE_LIMIT {
+        Some(TerminalKind::FiftyMoveRule)
+    } else {
+        None
+    }
+}
+
+/// How the game ends for `to_move`, or None while `has_moves` says it still has a legal move.
+fn ending(board: &rules::Board, to_move: Colour, has_moves: bool, antichess: bool) -> Option<TerminalKind> {
+    if has_moves {
+        return None;
+    }
+
+    // running out of pieces leaves no moves either
+    if antichess {
+        return Some(TerminalKind::NoMovesLeft(to_move));
+    }
+
+    if rules::in_check(board, to_move) {
+        Some(TerminalKind::Checkmate(rules::opponent(to_move)))
+    } else {
+        Some(TerminalKind::Stalemate)
+    }
+}
+
+/// Index into `castling_rights` of the castling a king moving from `from` to `to` performs, if it is one.
+fn castling_index(from: (usize, usize), to: (usize, usize)) -> Option<usize> {
+    CASTLING_HOME_SQUARES
+        .iter()
+        .position(|&[king, rook]| king == from && to == (from.0, if rook.1 == 7 { 6 } else { 2 }))
+}
+
+/// Castling rights left after a move from `from` to `to`. Any move from or onto a king or rook home square,
+/// such as capturing a rook where it started, loses the rights depending on it.
+fn castling_rights_after(mut rights: [bool; 4], from: (usize, usize), to: (usize, usize)) -> [bool; 4] {
+    for (right, homes) in rights.iter_mut().zip(CASTLING_HOME_SQUARES.iter()) {
+        if homes.contains(&from) || homes.contains(&to) {
+            *right = false;
+        }
+    }
+    rights
+}
+
+fn copy_to_clipboard(text: String) {
+    if let Err(e) = arboard::Clipboard::new().and_then(|mut c| c.set_text(text)) {
+        eprintln!("Warning: could not copy to the clipboard ({})", e);
+    }
+}
+
+fn paste_from_clipboard() -> Result<String, String> {
+    arboard::Clipboard::new()
+        .and_then(|mut c| c.get_text())
+        .map_err(|e| format!("could not read the clipboard ({})", e))
+}
+
+fn build_piece_batches(sprites: &HashMap<Piece, graphics::Image>) -> HashMap<Piece, SpriteBatch> {
+    sprites
+        .iter()
+        .map(|(piece, image)| (*piece, SpriteBatch::new(image.clone())))
+        .collect()
+}
+
+/// Scale of each sprite that makes its larger side as long as a tile.
+fn sprite_scales(sprites: &HashMap<Piece, graphics::Image>) -> HashMap<Piece, f32> {
+    sprites
+        .iter()
+        .map(|(piece, image)| (*piece, sprite_scale(image.width().max(image.height()), GRID_CELL_SIZE.0 as f32)))
+        .collect()
+}
+
+/// Scale that draws a sprite whose larger side is `side` pixels as large as a tile `tile` wide.
+fn sprite_scale(side: u16, tile: f32) -> f32 {
+    tile / side as f32
+}
+
+/// Scale and top left corner that draw a sprite of the given pixel `dimensions`, fitted to a tile by `tile_scale`,
+/// centred in a square of `size` with its top left corner at `pos`.
+fn sprite_placement(dimensions: (f32, f32), tile_scale: f32, pos: (f32, f32), size: f32) -> (f32, (f32, f32)) {
+    let scale = tile_scale * size / GRID_CELL_SIZE.0 as f32;
+    let (width, height) = (dimensions.0 * scale, dimensions.1 * scale);
+    (scale, (pos.0 + (size - width) / 2.0, pos.1 + (size - height) / 2.0))
+}
+
+fn sprite_filter(smooth: bool) -> graphics::FilterMode {
+    if smooth { graphics::FilterMode::Linear } else { graphics::FilterMode::Nearest }
+}
+
+/// Draws a rectangular outline just inside the edges of the tile with its top left corner at `corner`.
+fn draw_outline(ctx: &mut Context, corner: (f32, f32), width: f32, colour: graphics::Color) {
+    let outline = graphics::Mesh::new_rectangle(ctx,
+        graphics::DrawMode::stroke(width),
+        graphics::Rect::new(
+            corner.0 + width / 2.0,
+            corner.1 + width / 2.0,
+            GRID_CELL_SIZE.0 as f32 - width,
+            GRID_CELL_SIZE.1 as f32 - width,
+        ),
+        colour
+        ).expect("Failed to create outline.");
+    graphics::draw(ctx, &outline, graphics::DrawParam::default()).expect("Failed to draw outline.");
+}
+
+/// Draws an X across the tile with its top left corner at `corner`.
+fn draw_cross(ctx: &mut Context, corner: (f32, f32), colour: graphics::Color) {
+    let inset = GRID_CELL_SIZE.0 as f32 / 5.0;
+    let left = corner.0 + inset;
+    let top = corner.1 + inset;
+    let right = corner.0 + GRID_CELL_SIZE.0 as f32 - inset;
+    let bottom = corner.1 + GRID_CELL_SIZE.1 as f32 - inset;
+
+    let mut builder = graphics::MeshBuilder::new();
+    builder.line(&[[left, top], [right, bottom]], 6.0, colour).expect("Failed to create cross.");
+    builder.line(&[[right, top], [left, bottom]], 6.0, colour).expect("Failed to create cross.");
+    let cross = builder.build(ctx).expect("Failed to create cross.");
+    graphics::draw(ctx, &cross, graphics::DrawParam::default()).expect("Failed to draw cross.");
+}
+
+fn get_colour(piece: Piece) -> Option<Colour> {
+    match piece {
+        Piece::King(c)
+        | Piece::Queen(c)
+        | Piece::Rook(c)
+        | Piece::Knight(c)
+        | Piece::Bishop(c)
+        | Piece::Pawn(c) => Some(c),
+        Piece::Empty => None,
+    }
+}
+
+/// Name of a square in algebraic notation, e.g. `(6, 4)` is "e2".
+fn square_to_algebraic(square: (usize, usize)) -> String {
+    let mut string = String::new();
+
+    string.push(match square.1 {
+        0 => 'a',
+        1 => 'b',
+        2 => 'c',
+        3 => 'd',
+        4 => 'e',
+        5 => 'f',
+        6 => 'g',
         7 => 'h',
         _ => panic!("File wrong")
     });
-    match _to.0 {
-        0..=7 => string.push(char::from_digit(8 - _to.0 as u32, 10).unwrap()),
+    match square.0 {
+        0..=7 => string.push(char::from_digit(8 - square.0 as u32, 10).unwrap()),
         _ => panic!("Rank wrong"),
     };
 
     string
-}
\ No newline at end of file
+}
+
+fn move_string(_from: (usize, usize), _to: (usize, usize)) -> String  {
+    format!("{} {}", square_to_algebraic(_from), square_to_algebraic(_to))
+}
+
+/// String handed to `take_turn` for a move, with the letter of the piece a promoting pawn becomes after the squares.
+fn turn_string(from: (usize, usize), to: (usize, usize), promotion: Option<char>) -> String {
+    match promotion {
+        Some(letter) => format!("{} {}", move_string(from, to), letter),
+        None => move_string(from, to),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::temp_dir;
+
+    /// Whether the side to move has a legal move in standard chess.
+    fn has_legal_move(game: &Game) -> bool {
+        rules::all_squares()
+            .filter(|&pos| get_colour(game.board[pos.0][pos.1]) == Some(game.current_turn))
+            .any(|pos| !rules::legal_moves(game, pos, &rules::MoveRules::default()).is_empty())
+    }
+
+    fn game_ending(game: &Game) -> Option<TerminalKind> {
+        ending(&game.board, game.current_turn, has_legal_move(game), false)
+    }
+
+    /// Starting position with the pieces between both kings and their rooks taken off.
+    fn castling_position(to_move: Colour) -> Game {
+        let mut game = Game::new();
+        for &rank in [0, 7].iter() {
+            for &file in [1, 2, 3, 5, 6].iter() {
+                game.board[rank][file] = Piece::Empty;
+            }
+        }
+        game.current_turn = to_move;
+        game
+    }
+
+    /// Moves the engine offers the piece on `from` that leave its king out of check.
+    fn safe_moves(game: &Game, from: (usize, usize)) -> Vec<(usize, usize)> {
+        let colour = game.current_turn;
+        game.board[from.0][from.1]
+            .get_valid_moves(from, &game.board, game.en_passant_square, game.castlings, colour)
+            .into_iter()
+            .filter(|&to| rules::is_safe_move(&game.board, from, to, colour))
+            .collect()
+    }
+
+    /// Castling destinations the engine offers the king on `from` that leave it out of check.
+    fn castling_moves(game: &Game, from: (usize, usize)) -> Vec<(usize, usize)> {
+        safe_moves(game, from).into_iter().filter(|&to| castling_index(from, to).is_some()).collect()
+    }
+
+    /// Plays `moves` through the engine from the starting position and writes each in SAN the way make_move does.
+    fn san_moves(moves: &[&str]) -> Vec<String> {
+        let mut game = Game::new();
+        let mut sans = Vec::new();
+        for m in moves.iter() {
+            let (from, to) = (s(&m[0..2]), s(&m[3..5]));
+            let piece = game.board[from.0][from.1];
+            let others = (0..8)
+                .flat_map(|rank| (0..8).map(move |file| (rank, file)))
+                .filter(|&pos| pos != from && game.board[pos.0][pos.1] == piece && safe_moves(&game, pos).contains(&to))
+                .collect::<Vec<(usize, usize)>>();
+            let san = notation::san(&game.board, from, to, &others, 'Q');
+            game.take_turn(m.to_string());
+            sans.push(san + notation::check_suffix(&game.board, game.current_turn, has_legal_move(&game)));
+        }
+        sans
+    }
+
+    /// Window point at the centre of a square's tile, for a view with the given origin and scale.
+    fn tile_centre(square: (usize, usize), flipped: bool, origin: (f32, f32), scale: f32) -> (f32, f32) {
+        let corner = square_to_screen(square, flipped);
+        let centre = (corner.0 + GRID_CELL_SIZE.0 as f32 / 2.0, corner.1 + GRID_CELL_SIZE.1 as f32 / 2.0);
+        ((centre.0 + origin.0) * scale, (centre.1 + origin.1) * scale)
+    }
+
+    /// Checks that clicking the centre of every tile selects the square drawn there.
+    fn assert_round_trip(flipped: bool, origin: (f32, f32), scale: f32) {
+        for square in rules::all_squares() {
+            let (x, y) = tile_centre(square, flipped, origin, scale);
+            let (x, y) = window_to_board(x, y, origin, scale);
+            assert_eq!(screen_to_square(x, y, flipped), Some(square));
+        }
+    }
+
+    /// App state without a window or sprites, keeping its files in a temporary directory named after the test.
+    fn test_state(name: &str, config: &config::AppConfig) -> AppState {
+        AppState::with_sprites(HashMap::new(), config, temp_dir(name))
+    }
+
+    fn s(name: &str) -> (usize, usize) {
+        notation::square_from_algebraic(name).unwrap()
+    }
+
+    #[test]
+    fn sprites_all_present() {
+        let dir = temp_dir("sprites_present");
+        for (_, name) in SPRITE_FILES.iter() {
+            std::fs::write(dir.join(name.trim_start_matches('/')), b"").unwrap();
+        }
+        assert!(check_sprites(&dir).is_ok());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn sprite_missing_is_an_error() {
+        let dir = temp_dir("sprite_missing");
+        for (_, name) in SPRITE_FILES.iter().skip(1) {
+            std::fs::write(dir.join(name.trim_start_matches('/')), b"").unwrap();
+        }
+        assert_eq!(missing_sprites(&dir), vec![dir.join("black_king.png")]);
+        let loaded = AppState::load_sprites(&dir, |_path| panic!("loaded {} with a sprite missing", _path));
+        assert!(loaded.is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn fools_mate_is_checkmate_for_black() {
+        let mut game = Game::new();
+        for m in ["f2 f3", "e7 e5", "g2 g4", "d8 h4"].iter() {
+            game.take_turn(m.to_string());
+        }
+        assert!(game_ending(&game) == Some(TerminalKind::Checkmate(Colour::Black)));
+    }
+
+    #[test]
+    fn boxed_in_king_is_stalemate() {
+        let mut game = Game::new();
+        game.board = [[Piece::Empty; 8]; 8];
+        game.board[0][7] = Piece::King(Colour::Black);
+        game.board[1][5] = Piece::King(Colour::White);
+        game.board[2][6] = Piece::Queen(Colour::White);
+        game.current_turn = Colour::Black;
+        assert!(game_ending(&game) == Some(TerminalKind::Stalemate));
+    }
+
+    #[test]
+    fn running_out_of_pieces_wins_antichess() {
+        let mut board = [[Piece::Empty; 8]; 8];
+        board[0][0] = Piece::Rook(Colour::Black);
+        assert!(ending(&board, Colour::White, false, true) == Some(TerminalKind::NoMovesLeft(Colour::White)));
+        assert!(ending(&board, Colour::Black, true, true).is_none());
+    }
+
+    #[test]
+    fn rule_draws_become_claimable_at_their_limits() {
+        assert!(rule_draw(2, FIFTY_MOVE_LIMIT - 1).is_none());
+        assert!(rule_draw(3, 0) == Some(TerminalKind::Repetition));
+        assert!(rule_draw(1, FIFTY_MOVE_LIMIT) == Some(TerminalKind::FiftyMoveRule));
+    }
+
+    #[test]
+    fn draw_rules_can_be_switched_off() {
+        let config = config::AppConfig { repetition_draws: false, fifty_move_draws: false, ..config::AppConfig::default() };
+        let mut off = test_state("draw_rules_off", &config);
+        let mut on = test_state("draw_rules_on", &config::AppConfig::default());
+        off.halfmove_clock = FIFTY_MOVE_LIMIT;
+        on.halfmove_clock = FIFTY_MOVE_LIMIT;
+        assert!(off.claimable_draw().is_none() && off.draw_warning().is_none());
+        assert!(on.claimable_draw() == Some(TerminalKind::FiftyMoveRule));
+
+        // the starting position, come up for the third time
+        off.halfmove_clock = 0;
+        on.halfmove_clock = 0;
+        off.position_counts.insert(off.position_key(), 3);
+        on.position_counts.insert(on.position_key(), 3);
+        assert!(off.claimable_draw().is_none() && off.draw_warning().is_none());
+        assert!(on.claimable_draw() == Some(TerminalKind::Repetition));
+
+        std::fs::remove_dir_all(&off.resource_dir).unwrap();
+        std::fs::remove_dir_all(&on.resource_dir).unwrap();
+    }
+
+    /// App state with a pawn about to promote on `to`, the promotion dialog open for it.
+    fn promotion_pending(name: &str, fen: &str, from: &str, to: &str) -> AppState {
+        let mut state = test_state(name, &config::AppConfig::default());
+        state.load_fen(fen).unwrap();
+        state.select_square(s(from));
+        state.play_or_promote(s(from), s(to));
+        assert_eq!(state.ui.promotion_pending, Some(s(to)));
+        state
+    }
+
+    /// Square of the promotion dialog offering the piece with SAN letter `letter`.
+    fn promotion_square(state: &AppState, to: &str, letter: char) -> (usize, usize) {
+        state.promotion_squares(s(to)).into_iter().find(|&(_, (_, l))| l == letter).unwrap().0
+    }
+
+    #[test]
+    fn white_underpromotes_to_a_knight() {
+        let mut state = promotion_pending("promote_white", "k7/4P3/8/8/8/8/8/4K3 w - - 0 1", "e7", "e8");
+        let knight = promotion_square(&state, "e8", 'N');
+        assert_eq!(knight, s("e5"));
+        state.answer_promotion(s("e8"), knight);
+        assert_eq!(state.move_log.back(), Some(&("e7 e8 N".to_string(), true)));
+        assert!(state.game.board[0][4] == Piece::Knight(Colour::White));
+        assert_eq!(state.move_history, vec!["e8=N".to_string()]);
+
+        // the engine goes on to treat it as a knight
+        state.make_move(s("a8"), s("a7"), None);
+        state.make_move(s("e8"), s("f6"), None);
+        assert!(state.game.board[2][5] == Piece::Knight(Colour::White));
+        std::fs::remove_dir_all(&state.resource_dir).unwrap();
+    }
+
+    #[test]
+    fn black_underpromotes_to_a_rook() {
+        let mut state = promotion_pending("promote_black", "7k/8/8/8/8/8/3p4/K7 b - - 0 1", "d2", "d1");
+        let rook = promotion_square(&state, "d1", 'R');
+        assert_eq!(rook, s("d2"));
+        state.answer_promotion(s("d1"), rook);
+        assert_eq!(state.move_log.back(), Some(&("d2 d1 R".to_string(), true)));
+        assert!(state.game.board[7][3] == Piece::Rook(Colour::Black));
+        assert_eq!(state.move_history, vec!["d1=R+".to_string()]);
+        std::fs::remove_dir_all(&state.resource_dir).unwrap();
+    }
+
+    #[test]
+    fn promotion_piece_can_be_picked_from_the_keyboard() {
+        let mut state = promotion_pending("promote_keys", "k7/4P3/8/8/8/8/8/4K3 w - - 0 1", "e7", "e8");
+        state.cycle_destination(true);
+        assert_eq!(state.ui.promotion_focus, PROMOTION_CHOICES.len() - 1);
+        state.cycle_destination(false);
+        state.cycle_destination(false);
+        assert_eq!(state.ui.promotion_focus, 1);
+        std::fs::remove_dir_all(&state.resource_dir).unwrap();
+    }
+
+    #[test]
+    fn promotion_piece_can_be_picked_with_the_mouse_wheel() {
+        let mut state = promotion_pending("promote_wheel", "k7/4P3/8/8/8/8/8/4K3 w - - 0 1", "e7", "e8");
+        state.scroll_promotion(-1.0);
+        state.scroll_promotion(-1.0);
+        state.scroll_promotion(1.0);
+        assert_eq!(state.ui.promotion_focus, 1);
+
+        // confirmed as Return does, with the focused piece
+        let (rook, _) = state.promotion_squares(s("e8"))[state.ui.promotion_focus];
+        state.answer_promotion(s("e8"), rook);
+        assert!(state.game.board[0][4] == Piece::Rook(Colour::White));
+        std::fs::remove_dir_all(&state.resource_dir).unwrap();
+    }
+
+    #[test]
+    fn mouse_wheel_leaves_the_destinations_alone() {
+        let mut state = test_state("wheel_no_dialog", &config::AppConfig::default());
+        state.select_square(s("g1"));
+        state.scroll_promotion(-1.0);
+        assert_eq!(state.ui.focused_destination, None);
+        std::fs::remove_dir_all(&state.resource_dir).unwrap();
+    }
+
+    #[test]
+    fn clicking_off_the_promotion_dialog_cancels_it() {
+        let mut state = promotion_pending("promote_cancel", "k7/4P3/8/8/8/8/8/4K3 w - - 0 1", "e7", "e8");
+        state.answer_promotion(s("e8"), s("a1"));
+        assert_eq!(state.ui.promotion_pending, None);
+        assert!(state.game.board[1][4] == Piece::Pawn(Colour::White) && state.game.board[0][4] == Piece::Empty);
+        assert!(state.game.current_turn == Colour::White);
+        assert!(state.move_history.is_empty());
+        std::fs::remove_dir_all(&state.resource_dir).unwrap();
+    }
+
+    #[test]
+    fn auto_promote_skips_the_dialog() {
+        let config = config::AppConfig { auto_promote: Some('N'), ..config::AppConfig::default() };
+        let mut state = test_state("auto_promote", &config);
+        state.load_fen("k7/4P3/8/8/8/8/8/4K3 w - - 0 1").unwrap();
+        state.select_square(s("e7"));
+        state.play_or_promote(s("e7"), s("e8"));
+        assert_eq!(state.ui.promotion_pending, None);
+        assert_eq!(state.move_log.back(), Some(&("e7 e8 N".to_string(), true)));
+        assert!(state.game.board[0][4] == Piece::Knight(Colour::White));
+        std::fs::remove_dir_all(&state.resource_dir).unwrap();
+    }
+
+    #[test]
+    fn auto_promote_cycles_back_to_asking() {
+        let mut state = test_state("auto_promote_cycle", &config::AppConfig::default());
+        let mut seen = Vec::new();
+        for _ in 0..PROMOTION_CHOICES.len() + 1 {
+            state.cycle_auto_promote();
+            seen.push(state.auto_promote);
+        }
+        assert_eq!(seen, vec![Some('Q'), Some('R'), Some('B'), Some('N'), None]);
+        assert_eq!(config::AppConfig::load(&state.resource_dir).auto_promote, None);
+        std::fs::remove_dir_all(&state.resource_dir).unwrap();
+    }
+
+    #[test]
+    fn starting_position_has_not_ended() {
+        assert!(game_ending(&Game::new()).is_none());
+    }
+
+    #[test]
+    fn castling_offered_on_both_sides_for_both_colours() {
+        let white = castling_moves(&castling_position(Colour::White), s("e1"));
+        assert!(white.contains(&s("g1")) && white.contains(&s("c1")));
+        let black = castling_moves(&castling_position(Colour::Black), s("e8"));
+        assert!(black.contains(&s("g8")) && black.contains(&s("c8")));
+    }
+
+    #[test]
+    fn castling_destinations_map_to_their_rights() {
+        assert_eq!(castling_index(s("e1"), s("g1")), Some(0));
+        assert_eq!(castling_index(s("e1"), s("c1")), Some(1));
+        assert_eq!(castling_index(s("e8"), s("g8")), Some(2));
+        assert_eq!(castling_index(s("e8"), s("c8")), Some(3));
+        assert_eq!(castling_index(s("e1"), s("f1")), None);
+    }
+
+    #[test]
+    fn capturing_a_rook_at_home_loses_its_castling_right() {
+        // a black bishop takes the rook on h1, a white knight the rook on a8
+        assert_eq!(castling_rights_after([true; 4], s("c6"), s("h1")), [false, true, true, true]);
+        assert_eq!(castling_rights_after([true; 4], s("b6"), s("a8")), [true, true, true, false]);
+    }
+
+    #[test]
+    fn king_move_loses_both_castling_rights() {
+        assert_eq!(castling_rights_after([true; 4], s("e8"), s("e7")), [true, true, false, false]);
+    }
+
+    #[test]
+    fn game_with_castling_a_capture_a_qualified_move_and_a_check() {
+        let moves = [
+            "e2 e4", "e7 e5", "g1 f3", "b8 c6", "f1 b5", "g8 f6", "e1 g1", "f6 e4",
+            "d2 d4", "f8 c5", "b1 d2", "c5 f2", "f1 f2",
+        ];
+        let expected = ["e4", "e5", "Nf3", "Nc6", "Bb5", "Nf6", "O-O", "Nxe4", "d4", "Bc5", "Nbd2", "Bxf2+", "Rxf2"];
+        assert_eq!(san_moves(&moves), expected.iter().map(|m| m.to_string()).collect::<Vec<String>>());
+    }
+
+    #[test]
+    fn tile_centres_round_trip() {
+        assert_round_trip(false, (0.0, 0.0), 1.0);
+    }
+
+    #[test]
+    fn tile_centres_round_trip_with_offset_origin() {
+        assert_round_trip(false, (120.0, 35.0), 0.75);
+    }
+
+    #[test]
+    fn leaving_free_play_turns_the_board_back_with_auto_flip() {
+        let config = config::AppConfig { auto_flip: true, intro_animation: false, ..config::AppConfig::default() };
+        let mut state = test_state("free_play_flip", &config);
+        state.toggle_free_play();
+        state.make_move(s("e2"), s("e4"), None);
+        assert!(state.flipped);
+        state.toggle_free_play();
+        assert!(state.game.current_turn == Colour::White && !state.flipped);
+        std::fs::remove_dir_all(&state.resource_dir).unwrap();
+    }
+
+    #[test]
+    fn review_steps_back_and_turns_with_review_flip() {
+        let config = config::AppConfig { review_flip: true, intro_animation: false, ..config::AppConfig::default() };
+        let mut state = test_state("review_flip", &config);
+        state.make_move(s("e2"), s("e4"), None);
+        state.make_move(s("e7"), s("e5"), None);
+        let game_board = state.game.board;
+
+        // back to the position after 1.e4, black to move
+        state.step_review(true);
+        assert!(state.game.board == state.positions[1].0 && state.game.current_turn == Colour::Black);
+        assert!(state.flipped);
+        state.step_review(true);
+        assert!(state.game.board == Game::new().board && !state.flipped);
+        // the start is as far back as it goes
+        state.step_review(true);
+        assert_eq!(state.review.as_ref().map(|r| r.index), Some(0));
+
+        // nothing can be played from an earlier position
+        state.select_square(s("e2"));
+        assert_eq!(state.ui.selected_square, None);
+
+        state.step_review(false);
+        assert!(state.flipped);
+        state.step_review(false);
+        assert!(state.review.is_none());
+        assert!(state.game.board == game_board && state.game.current_turn == Colour::White && !state.flipped);
+        assert_eq!(state.last_move, Some((s("e7"), s("e5"))));
+        std::fs::remove_dir_all(&state.resource_dir).unwrap();
+    }
+
+    #[test]
+    fn review_ignores_auto_flip() {
+        let config = config::AppConfig { auto_flip: true, intro_animation: false, ..config::AppConfig::default() };
+        let mut state = test_state("review_auto_flip", &config);
+        state.make_move(s("e2"), s("e4"), None);
+        assert!(state.flipped);
+        // white to move at the start, but without review_flip the board stays as it was
+        state.step_review(true);
+        assert!(state.game.current_turn == Colour::White && state.flipped);
+        state.step_review(false);
+        assert!(state.review.is_none() && state.flipped);
+        std::fs::remove_dir_all(&state.resource_dir).unwrap();
+    }
+
+    #[test]
+    fn new_game_ends_a_review() {
+        let config = config::AppConfig { review_flip: true, intro_animation: false, ..config::AppConfig::default() };
+        let mut state = test_state("review_new_game", &config);
+        state.make_move(s("e2"), s("e4"), None);
+        state.make_move(s("e7"), s("e5"), None);
+        state.step_review(true);
+        assert!(state.flipped);
+        state.new_game();
+        assert!(state.review.is_none() && !state.flipped);
+        assert_eq!(state.positions.len(), 1);
+        std::fs::remove_dir_all(&state.resource_dir).unwrap();
+    }
+
+    #[test]
+    fn volume_keys_turn_the_sound_back_on_and_are_remembered() {
+        let mut state = test_state("volume", &config::AppConfig::default());
+        state.toggle_sound();
+        assert!(!state.sound);
+        state.step_volume(true);
+        assert!(state.sound);
+        let saved = config::AppConfig::load(&state.resource_dir);
+        assert!(saved.sound);
+        assert!((saved.volume - 0.6).abs() < 1e-6);
+        std::fs::remove_dir_all(&state.resource_dir).unwrap();
+    }
+
+    #[test]
+    fn tile_centres_round_trip_flipped() {
+        assert_round_trip(true, (0.0, 0.0), 1.0);
+        assert_round_trip(true, (120.0, 35.0), 0.75);
+    }
+
+    #[test]
+    fn flipped_board_has_h1_at_the_top_left() {
+        assert_eq!(screen_to_square(1.0, 1.0, true), Some((7, 7)));
+        assert_eq!(square_to_screen((0, 0), true), (7.0 * GRID_CELL_SIZE.0 as f32, 7.0 * GRID_CELL_SIZE.1 as f32));
+    }
+
+    #[test]
+    fn edge_labels_turn_with_the_board() {
+        let files = |flipped| (0..GRID_SIZE as usize).map(|i| file_label(i, flipped)).collect::<String>();
+        let ranks = |flipped| (0..GRID_SIZE as usize).map(|i| rank_label(i, flipped)).collect::<String>();
+        assert_eq!((files(false), ranks(false)), ("abcdefgh".to_string(), "87654321".to_string()));
+        assert_eq!((files(true), ranks(true)), ("hgfedcba".to_string(), "12345678".to_string()));
+    }
+
+    #[test]
+    fn edge_labels_name_the_squares_they_sit_on() {
+        let last = GRID_SIZE as usize - 1;
+        for &flipped in [false, true].iter() {
+            for i in 0..GRID_SIZE as usize {
+                let bottom = square_to_algebraic(flip((last, i), flipped));
+                let left = square_to_algebraic(flip((i, 0), flipped));
+                assert_eq!(file_label(i, flipped), bottom.chars().next().unwrap());
+                assert_eq!(rank_label(i, flipped), left.chars().nth(1).unwrap());
+            }
+        }
+    }
+
+    #[test]
+    fn points_off_the_board_have_no_square() {
+        assert_eq!(screen_to_square(-1.0, 10.0, false), None);
+        assert_eq!(screen_to_square(10.0, GRID_SIZE as f32 * GRID_CELL_SIZE.1 as f32, false), None);
+    }
+
+    #[test]
+    fn clicks_in_a_wide_window() {
+        let (origin, scale) = letterbox(SCREEN_SIZE.0 * 3.0, SCREEN_SIZE.1 * 1.5).unwrap();
+        assert_eq!(scale, 1.5);
+        assert_eq!(origin, (SCREEN_SIZE.0 / 2.0, 0.0));
+        assert_round_trip(false, origin, scale);
+
+        // the bars either side of the board
+        let (x, y) = window_to_board(10.0, 100.0, origin, scale);
+        assert_eq!(screen_to_square(x, y, false), None);
+        let (x, y) = window_to_board(SCREEN_SIZE.0 * 3.0 - 10.0, 100.0, origin, scale);
+        assert_eq!(screen_to_square(x, y, false), None);
+    }
+
+    #[test]
+    fn clicks_in_a_tall_window() {
+        let (origin, scale) = letterbox(SCREEN_SIZE.0 * 0.5, SCREEN_SIZE.1 * 2.0).unwrap();
+        assert_eq!(scale, 0.5);
+        assert_eq!(origin, (0.0, SCREEN_SIZE.1 * 1.5));
+        assert_round_trip(false, origin, scale);
+
+        // the bar above the board
+        let (x, y) = window_to_board(100.0, 10.0, origin, scale);
+        assert_eq!(screen_to_square(x, y, false), None);
+    }
+
+    #[test]
+    fn empty_window_has_no_view() {
+        assert!(letterbox(0.0, 0.0).is_none());
+        assert!(letterbox(800.0, 0.0).is_none());
+    }
+
+    #[test]
+    fn new_game_during_animations() {
+        let config = config::AppConfig { intro_animation: false, ..config::AppConfig::default() };
+        let mut state = test_state("new_game_animations", &config);
+        state.make_move(s("e2"), s("e4"), None);
+
+        // a knight lifted and selected, with the previous move still settling and pieces dropping in
+        state.select_square(s("g8"));
+        state.ui.lift_offset = LIFT_HEIGHT / 2.0;
+        state.ui.fading_tiles = vec![(s("e2"), SELECTED)];
+        state.ui.drop_offsets[0][0] = GRID_CELL_SIZE.1 as f32;
+        state.toggle_analysis_overlay(s("b8"));
+        assert!(state.completed_move.is_some() && !state.ui.highlighted_squares.is_empty());
+
+        state.new_game();
+        assert_eq!(state.ui.drop_offsets, [[0.0; 8]; 8]);
+        assert_eq!((state.ui.lift_square, state.ui.lift_offset), (None, 0.0));
+        assert_eq!(state.ui.selected_square, None);
+        assert!(state.ui.highlighted_squares.is_empty() && state.ui.fading_tiles.is_empty());
+        assert!(state.ui.analysis_overlays.is_empty());
+        assert!(state.completed_move.is_none());
+        assert!(state.game.board == Game::new().board);
+        assert!(state.move_history.is_empty());
+        std::fs::remove_dir_all(&state.resource_dir).unwrap();
+    }
+
+    #[test]
+    fn openings_reach_their_positions() {
+        let positions = [
+            "r1bqkbnr/pppp1ppp/2n5/4p3/2B1P3/5N2/PPPP1PPP/RNBQK2R b KQkq - 3 3",
+            "r1bqkbnr/pppp1ppp/2n5/1B2p3/4P3/5N2/PPPP1PPP/RNBQK2R b KQkq - 3 3",
+            "rnbqkbnr/pp1ppppp/8/2p5/4P3/8/PPPP1PPP/RNBQKBNR w KQkq c6 0 2",
+            "rnbqkbnr/ppp2ppp/4p3/3p4/3PP3/8/PPP2PPP/RNBQKBNR w KQkq d6 0 3",
+            "rnbqkbnr/ppp1pppp/8/3p4/2PP4/8/PP2PPPP/RNBQKBNR b KQkq c3 0 2",
+        ];
+        assert_eq!(positions.len(), OPENINGS.len());
+
+        let mut state = test_state("openings", &config::AppConfig::default());
+        for (index, ((name, moves), fen)) in OPENINGS.iter().zip(positions.iter()).enumerate() {
+            state.load_opening(index);
+            let position = notation::parse_fen(fen).unwrap();
+            assert_eq!(state.moves_played as usize, moves.len(), "{} stopped early", name);
+            assert!(state.game.board == position.board, "{} reached the wrong position", name);
+            assert!(state.game.current_turn == position.to_move, "{} left the wrong side to move", name);
+        }
+        std::fs::remove_dir_all(&state.resource_dir).unwrap();
+    }
+
+    #[test]
+    fn sprite_sets_of_any_resolution_fill_a_tile() {
+        let tile = GRID_CELL_SIZE.0 as f32;
+        let corner = square_to_screen((3, 2), false);
+        for &side in [64u16, 128].iter() {
+            let (scale, dest) = sprite_placement((side as f32, side as f32), sprite_scale(side, tile), corner, tile);
+            assert_eq!(side as f32 * scale, tile);
+            assert_eq!(dest, corner);
+        }
+    }
+
+    #[test]
+    fn narrow_sprites_are_centred_in_their_tile() {
+        let tile = GRID_CELL_SIZE.0 as f32;
+        let corner = square_to_screen((3, 2), false);
+        // 64 and 128 pixels tall but only three quarters as wide, leaving an eighth of the tile either side
+        for &(width, height) in [(48u16, 64u16), (96, 128)].iter() {
+            let (scale, dest) = sprite_placement((width as f32, height as f32), sprite_scale(height, tile), corner, tile);
+            assert_eq!(height as f32 * scale, tile);
+            assert_eq!(dest, (corner.0 + tile / 8.0, corner.1));
+        }
+    }
+}
@@ -0,0 +1,395 @@
+/**
+ * Text representations of positions.
+ */
+
+use murnion_chess::{Colour, Piece};
+use crate::rules::Board;
+
+/// Letter for a piece, uppercase for white and lowercase for black.
+pub fn piece_letter(piece: Piece) -> char {
+    let (letter, colour) = match piece {
+        Piece::King(c) => ('K', c),
+        Piece::Queen(c) => ('Q', c),
+        Piece::Rook(c) => ('R', c),
+        Piece::Bishop(c) => ('B', c),
+        Piece::Knight(c) => ('N', c),
+        Piece::Pawn(c) => ('P', c),
+        Piece::Empty => return '.',
+    };
+    if colour == Colour::White { letter } else { letter.to_ascii_lowercase() }
+}
+
+/// Text diagram of the board with rank and file labels, headed by the side to move.
+/// A `flipped` board is drawn turned around as on screen, with black at the bottom.
+pub fn board_ascii(board: &Board, to_move: Colour, flipped: bool) -> String {
+    let order = |i: usize| if flipped { 7 - i } else { i };
+    let mut string = String::new();
+    string.push_str(if to_move == Colour::White { "White to move\n" } else { "Black to move\n" });
+    string.push_str("  +-----------------+\n");
+    for rank in (0..8).map(order) {
+        string.push_str(&format!("{} |", 8 - rank));
+        for file in (0..8).map(order) {
+            string.push(' ');
+            string.push(piece_letter(board[rank][file]));
+        }
+        string.push_str(" |\n");
+    }
+    string.push_str("  +-----------------+\n");
+    string.push_str("   ");
+    for file in (0..8).map(order) {
+        string.push(' ');
+        string.push((b'a' + file as u8) as char);
+    }
+    string.push('\n');
+    string
+}
+
+/// A position read from FEN. Move counters are not kept.
+pub struct Position {
+    pub board: Board,
+    pub to_move: Colour,
+    /// Castling rights in "KQkq" order.
+    pub castling: [bool; 4],
+    pub en_passant: Option<(usize, usize)>,
+}
+
+fn piece_from_letter(letter: char) -> Option<Piece> {
+    let colour = if letter.is_ascii_uppercase() { Colour::White } else { Colour::Black };
+    match letter.to_ascii_uppercase() {
+        'K' => Some(Piece::King(colour)),
+        'Q' => Some(Piece::Queen(colour)),
+        'R' => Some(Piece::Rook(colour)),
+        'B' => Some(Piece::Bishop(colour)),
+        'N' => Some(Piece::Knight(colour)),
+        'P' => Some(Piece::Pawn(colour)),
+        _ => None,
+    }
+}
+
+/// Board coordinates of an algebraic square name such as "e3".
+pub fn square_from_algebraic(name: &str) -> Option<(usize, usize)> {
+    let mut chars = name.chars();
+    let file = chars.next()?;
+    let rank = chars.next()?.to_digit(10)?;
+    if chars.next().is_some() || !('a'..='h').contains(&file) || !(1..=8).contains(&rank) {
+        return None;
+    }
+    Some((8 - rank as usize, file as usize - 'a' as usize))
+}
+
+/// Parses the placement, side to move, castling and en passant fields of a FEN string.
+/// Placements the GUI can't play from, such as a missing king, are rejected as well.
+pub fn parse_fen(fen: &str) -> Result<Position, String> {
+    let fields = fen.split_whitespace().collect::<Vec<&str>>();
+    if fields.len() < 2 {
+        return Err("expected at least piece placement and side to move".to_string());
+    }
+
+    let rows = fields[0].split('/').collect::<Vec<&str>>();
+    if rows.len() != 8 {
+        return Err(format!("expected 8 ranks, found {}", rows.len()));
+    }
+    let mut board = [[Piece::Empty; 8]; 8];
+    for (rank, row) in rows.iter().enumerate() {
+        let mut file = 0;
+        for c in row.chars() {
+            if let Some(n) = c.to_digit(10) {
+                if n == 0 {
+                    return Err(format!("rank {} has a run of 0 empty squares", 8 - rank));
+                }
+                file += n as usize;
+            } else {
+                let piece = piece_from_letter(c).ok_or(format!("unknown piece '{}'", c))?;
+                if file < 8 {
+                    board[rank][file] = piece;
+                }
+                file += 1;
+            }
+        }
+        if file != 8 {
+            return Err(format!("rank {} has {} squares", 8 - rank, file));
+        }
+    }
+
+    crate::rules::check_board(&board)?;
+
+    let to_move = match fields[1] {
+        "w" => Colour::White,
+        "b" => Colour::Black,
+        other => return Err(format!("unknown side to move '{}'", other)),
+    };
+
+    let mut castling = [false; 4];
+    if let Some(&field) = fields.get(2) {
+        for c in field.chars().filter(|&c| c != '-') {
+            match "KQkq".find(c) {
+                Some(i) => castling[i] = true,
+                None => return Err(format!("unknown castling right '{}'", c)),
+            }
+        }
+    }
+
+    let en_passant = match fields.get(3) {
+        None | Some(&"-") => None,
+        Some(name) => match square_from_algebraic(name) {
+            // only a double pawn push, to rank 4 or 5, leaves a square behind it to take on
+            Some(square) if square.0 == 2 || square.0 == 5 => Some(square),
+            _ => return Err(format!("bad en passant square '{}'", name)),
+        },
+    };
+
+    Ok(Position { board, to_move, castling, en_passant })
+}
+
+/// Moves in SAN on one line with move numbers, e.g. "1.e4 e5 2.Nf3 Nc6 *", ending with the `result` token.
+/// `black_first` numbers the first move as black's, for games set up with black to move.
+pub fn compact_moves(moves: &[String], black_first: bool, result: &str) -> String {
+    let mut parts = Vec::with_capacity(moves.len() + 1);
+    let offset = if black_first { 1 } else { 0 };
+    for (i, san) in moves.iter().enumerate() {
+        let ply = i + offset;
+        if ply % 2 == 0 {
+            parts.push(format!("{}.{}", ply / 2 + 1, san));
+        } else if i == 0 {
+            parts.push(format!("1...{}", san));
+        } else {
+            parts.push(san.clone());
+        }
+    }
+    parts.push(result.to_string());
+    parts.join(" ")
+}
+
+/// Standard algebraic notation for moving the piece on `from` to `to`, without the check or mate suffix.
+/// `others` are the squares of other pieces of the same kind and colour that could also move to `to`,
+/// and `promotion` the letter of the piece a pawn reaching the last rank becomes.
+pub fn san(board: &Board, from: (usize, usize), to: (usize, usize), others: &[(usize, usize)], promotion: char) -> String {
+    let piece = board[from.0][from.1];
+    let destination = crate::square_to_algebraic(to);
+    let file = (b'a' + from.1 as u8) as char;
+
+    if let Piece::King(_) = piece {
+        if from.1 == 4 && to.1 == 6 {
+            return "O-O".to_string();
+        } else if from.1 == 4 && to.1 == 2 {
+            return "O-O-O".to_string();
+        }
+    }
+
+    if let Piece::Pawn(_) = piece {
+        // pawns only ever capture diagonally, en passant onto an empty square
+        let mut string = if from.1 != to.1 { format!("{}x{}", file, destination) } else { destination };
+        if to.0 == 0 || to.0 == 7 {
+            string.push('=');
+            string.push(promotion);
+        }
+        return string;
+    }
+
+    let mut string = piece_letter(piece).to_ascii_uppercase().to_string();
+    if !others.is_empty() {
+        if others.iter().all(|o| o.1 != from.1) {
+            string.push(file);
+        } else if others.iter().all(|o| o.0 != from.0) {
+            string.push_str(&(8 - from.0).to_string());
+        } else {
+            string.push(file);
+            string.push_str(&(8 - from.0).to_string());
+        }
+    }
+    if board[to.0][to.1] != Piece::Empty {
+        string.push('x');
+    }
+    string.push_str(&destination);
+    string
+}
+
+/// "#" for a move that mates, "+" for one that only checks, or nothing, given the board after it and whether
+/// the side now to move has a legal move.
+pub fn check_suffix(board: &Board, to_move: Colour, has_moves: bool) -> &'static str {
+    if !crate::rules::in_check(board, to_move) {
+        ""
+    } else if has_moves {
+        "+"
+    } else {
+        "#"
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rules::board_with;
+    use murnion_chess::Game;
+
+    fn moves(sans: &[&str]) -> Vec<String> {
+        sans.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn s(name: &str) -> (usize, usize) {
+        square_from_algebraic(name).unwrap()
+    }
+
+    #[test]
+    fn parse_fen_reads_a_position() {
+        let position = parse_fen("4k3/8/8/3pP3/8/8/8/4K2R w K d6 0 1").unwrap();
+        assert!(position.board[7][7] == Piece::Rook(Colour::White));
+        assert!(position.board[3][3] == Piece::Pawn(Colour::Black));
+        assert!(position.to_move == Colour::White);
+        assert_eq!(position.castling, [true, false, false, false]);
+        assert_eq!(position.en_passant, square_from_algebraic("d6"));
+    }
+
+    #[test]
+    fn parse_fen_rejects_unplayable_positions() {
+        for fen in [
+            "8/8/8/8/8/8/8/4K3 w - -",
+            "4k3/8/8/8/8/8/8/3KK3 w - -",
+            "3Pk3/8/8/8/8/8/8/4K3 w - -",
+            "4k3/8/8/8/8/8/8/p3K3 b - -",
+            "4k3/8/8/8/8/8/8/4K3 x - -",
+            "4k3/8/8/8/8/8/4K3 w - -",
+            "4k3/9/8/8/8/8/8/4K3 w - -",
+        ].iter() {
+            assert!(parse_fen(fen).is_err(), "{} was accepted", fen);
+        }
+    }
+
+    #[test]
+    fn compact_moves_numbers_white_first() {
+        let line = compact_moves(&moves(&["e4", "e5", "Nf3", "Nc6", "Bb5"]), false, "*");
+        assert_eq!(line, "1.e4 e5 2.Nf3 Nc6 3.Bb5 *");
+    }
+
+    #[test]
+    fn compact_moves_numbers_black_first() {
+        let line = compact_moves(&moves(&["e5", "Nf3", "Nc6"]), true, "*");
+        assert_eq!(line, "1...e5 2.Nf3 Nc6 *");
+    }
+
+    #[test]
+    fn compact_moves_ends_with_result() {
+        let line = compact_moves(&moves(&["f3", "e5", "g4", "Qh4#"]), false, "0-1");
+        assert_eq!(line, "1.f3 e5 2.g4 Qh4# 0-1");
+        assert_eq!(compact_moves(&[], false, "1/2-1/2"), "1/2-1/2");
+    }
+
+    #[test]
+    fn san_qualifies_knights_by_file() {
+        let board = board_with(&[("b1", Piece::Knight(Colour::White)), ("f3", Piece::Knight(Colour::White))]);
+        assert_eq!(san(&board, s("b1"), s("d2"), &[s("f3")], 'Q'), "Nbd2");
+    }
+
+    #[test]
+    fn san_qualifies_knights_on_one_file_by_rank() {
+        let board = board_with(&[("g1", Piece::Knight(Colour::White)), ("g5", Piece::Knight(Colour::White))]);
+        assert_eq!(san(&board, s("g1"), s("f3"), &[s("g5")], 'Q'), "N1f3");
+    }
+
+    #[test]
+    fn san_qualifies_rooks_on_one_file_by_rank() {
+        let board = board_with(&[("a1", Piece::Rook(Colour::White)), ("a5", Piece::Rook(Colour::White))]);
+        assert_eq!(san(&board, s("a1"), s("a3"), &[s("a5")], 'Q'), "R1a3");
+    }
+
+    #[test]
+    fn san_of_pawn_captures() {
+        let capture = board_with(&[("e4", Piece::Pawn(Colour::White)), ("d5", Piece::Pawn(Colour::Black))]);
+        assert_eq!(san(&capture, s("e4"), s("d5"), &[], 'Q'), "exd5");
+
+        let en_passant = board_with(&[("e5", Piece::Pawn(Colour::White)), ("d5", Piece::Pawn(Colour::Black))]);
+        assert_eq!(san(&en_passant, s("e5"), s("d6"), &[], 'Q'), "exd6");
+    }
+
+    #[test]
+    fn san_of_castling() {
+        let board = board_with(&[
+            ("e1", Piece::King(Colour::White)),
+            ("a1", Piece::Rook(Colour::White)),
+            ("h1", Piece::Rook(Colour::White)),
+        ]);
+        assert_eq!(san(&board, s("e1"), s("g1"), &[], 'Q'), "O-O");
+        assert_eq!(san(&board, s("e1"), s("c1"), &[], 'Q'), "O-O-O");
+    }
+
+    #[test]
+    fn san_of_promotion_with_check() {
+        let before = board_with(&[
+            ("e7", Piece::Pawn(Colour::White)),
+            ("a1", Piece::King(Colour::White)),
+            ("h8", Piece::King(Colour::Black)),
+        ]);
+        let after = board_with(&[
+            ("e8", Piece::Queen(Colour::White)),
+            ("a1", Piece::King(Colour::White)),
+            ("h8", Piece::King(Colour::Black)),
+        ]);
+        let string = san(&before, s("e7"), s("e8"), &[], 'Q') + check_suffix(&after, Colour::Black, true);
+        assert_eq!(string, "e8=Q+");
+    }
+
+    #[test]
+    fn san_of_mate() {
+        let before = board_with(&[("a1", Piece::Rook(Colour::White)), ("h8", Piece::King(Colour::Black))]);
+        let after = board_with(&[("a8", Piece::Rook(Colour::White)), ("h8", Piece::King(Colour::Black))]);
+        assert_eq!(san(&before, s("a1"), s("a8"), &[], 'Q') + check_suffix(&after, Colour::Black, false), "Ra8#");
+        assert_eq!(check_suffix(&before, Colour::Black, true), "");
+    }
+
+    #[test]
+    fn parse_fen_rejects_a_run_of_no_squares() {
+        assert!(parse_fen("4k3/08/8/8/8/8/8/4K3 w - -").is_err());
+        assert!(parse_fen("4k03/8/8/8/8/8/8/4K3 w - -").is_err());
+    }
+
+    #[test]
+    fn parse_fen_wants_en_passant_on_rank_3_or_6() {
+        assert!(parse_fen("4k3/8/8/8/3Pp3/8/8/4K3 b - d3").is_ok());
+        for fen in [
+            "4k3/8/8/3pP3/8/8/8/4K3 w - d5",
+            "4k3/8/8/3pP3/8/8/8/4K3 w - d8",
+            "4k3/8/8/3pP3/8/8/8/4K3 w - d1",
+        ].iter() {
+            assert!(parse_fen(fen).is_err(), "{} was accepted", fen);
+        }
+    }
+
+    #[test]
+    fn board_ascii_of_starting_position() {
+        let expected = "\
+White to move
+  +-----------------+
+8 | r n b q k b n r |
+7 | p p p p p p p p |
+6 | . . . . . . . . |
+5 | . . . . . . . . |
+4 | . . . . . . . . |
+3 | . . . . . . . . |
+2 | P P P P P P P P |
+1 | R N B Q K B N R |
+  +-----------------+
+    a b c d e f g h
+";
+        assert_eq!(board_ascii(&Game::new().board, Colour::White, false), expected);
+    }
+
+    #[test]
+    fn board_ascii_flipped() {
+        let position = parse_fen("4k3/8/8/8/8/8/8/R3K3 b Q - 0 1").unwrap();
+        let expected = "\
+Black to move
+  +-----------------+
+1 | . . . K . . . R |
+2 | . . . . . . . . |
+3 | . . . . . . . . |
+4 | . . . . . . . . |
+5 | . . . . . . . . |
+6 | . . . . . . . . |
+7 | . . . . . . . . |
+8 | . . . k . . . . |
+  +-----------------+
+    h g f e d c b a
+";
+        assert_eq!(board_ascii(&position.board, position.to_move, true), expected);
+    }
+}
@@ -0,0 +1,93 @@
+/**
+ * Coordinate quiz: click the square whose name is shown, as many as possible before the round ends.
+ */
+
+use crate::rng::XorShift;
+use std::time::{Duration, Instant};
+
+pub const ROUND_DURATION: Duration = Duration::from_secs(30);
+/// How long a clicked square flashes green or red.
+pub const FLASH_DURATION: Duration = Duration::from_millis(300);
+
+pub struct Quiz {
+    /// Square to find next.
+    pub target: (usize, usize),
+    pub correct: u32,
+    pub incorrect: u32,
+    /// Draw the board without pieces.
+    pub hide_pieces: bool,
+    /// Last clicked square, whether it was right, and when.
+    pub flash: Option<((usize, usize), bool, Instant)>,
+    /// Whether the finished round has been counted towards the best score.
+    pub scored: bool,
+    started: Instant,
+    /// When the round was paused, if it is.
+    paused_at: Option<Instant>,
+    rng: XorShift,
+}
+
+impl Quiz {
+    pub fn new(hide_pieces: bool) -> Quiz {
+        let mut quiz = Quiz {
+            target: (0, 0),
+            correct: 0,
+            incorrect: 0,
+            hide_pieces,
+            flash: None,
+            scored: false,
+            started: Instant::now(),
+            paused_at: None,
+            rng: XorShift::from_clock(),
+        };
+        quiz.next_target();
+        quiz
+    }
+
+    /// Picks a new square to find, never the same one twice in a row.
+    fn next_target(&mut self) {
+        loop {
+            let index = self.rng.below(64);
+            let square = (index / 8, index % 8);
+            if square != self.target || self.correct + self.incorrect == 0 {
+                self.target = square;
+                return;
+            }
+        }
+    }
+
+    /// Scores a click on `square` and moves on to the next target, unless the round is over.
+    pub fn answer(&mut self, square: (usize, usize)) {
+        if self.is_over() {
+            return;
+        }
+        let right = square == self.target;
+        if right {
+            self.correct += 1;
+        } else {
+            self.incorrect += 1;
+        }
+        self.flash = Some((square, right, Instant::now()));
+        self.next_target();
+    }
+
+    /// Stops the round timer until `resume`.
+    pub fn pause(&mut self) {
+        self.paused_at.get_or_insert_with(Instant::now);
+    }
+
+    /// Restarts the round timer, giving back the time spent paused.
+    pub fn resume(&mut self) {
+        if let Some(at) = self.paused_at.take() {
+            self.started += at.elapsed();
+        }
+    }
+
+    pub fn time_left(&self) -> Duration {
+        let elapsed = self.paused_at.unwrap_or_else(Instant::now).duration_since(self.started);
+        ROUND_DURATION.checked_sub(elapsed).unwrap_or_default()
+    }
+
+    pub fn is_over(&self) -> bool {
+        self.time_left() == Duration::from_secs(0)
+    }
+}
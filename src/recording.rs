@@ -0,0 +1,115 @@
+/**
+ * Recording and playback of input events, for reproducing bugs.
+ * Recordings are JSON lines, one event per line with the milliseconds since the recording started.
+ */
+
+use ggez::event::MouseButton;
+use serde::{Deserialize, Serialize};
+use std::{collections::VecDeque, fs, io::{self, Write}, path::Path, time::Instant};
+
+/// A single input event. Coordinates are window coordinates, so playback needs the same window size.
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum InputEvent {
+    MouseUp { button: String, x: f32, y: f32 },
+    MouseMotion { x: f32, y: f32 },
+    MouseWheel { y: f32 },
+    KeyDown { key: String, shift: bool },
+}
+
+#[derive(Serialize, Deserialize)]
+struct TimedEvent {
+    t: u64,
+    #[serde(flatten)]
+    event: InputEvent,
+}
+
+/// Appends input events to a recording file as they happen.
+pub struct Recorder {
+    file: io::LineWriter<fs::File>,
+    start: Instant,
+}
+
+impl Recorder {
+    pub fn create(path: &Path) -> io::Result<Recorder> {
+        Ok(Recorder {
+            file: io::LineWriter::new(fs::File::create(path)?),
+            start: Instant::now(),
+        })
+    }
+
+    /// Writes an event, flushed immediately so a crash doesn't lose it.
+    pub fn record(&mut self, event: InputEvent) {
+        let timed = TimedEvent { t: self.start.elapsed().as_millis() as u64, event };
+        let result = serde_json::to_string(&timed)
+            .map_err(|e| e.to_string())
+            .and_then(|line| writeln!(self.file, "{}", line).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            eprintln!("Warning: could not record input event ({})", e);
+        }
+    }
+
+    pub fn flush(&mut self) {
+        if let Err(e) = self.file.flush() {
+            eprintln!("Warning: could not flush recording ({})", e);
+        }
+    }
+}
+
+/// Feeds the events of a recording back at their recorded times.
+pub struct Playback {
+    events: VecDeque<TimedEvent>,
+    /// Set on the first poll, so loading doesn't eat into the recorded timing.
+    start: Option<Instant>,
+}
+
+impl Playback {
+    /// Reads a recording, skipping malformed lines with a warning.
+    pub fn load(path: &Path) -> io::Result<Playback> {
+        let mut events = VecDeque::new();
+        for (i, line) in fs::read_to_string(path)?.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<TimedEvent>(line) {
+                Ok(e) => events.push_back(e),
+                Err(e) => eprintln!("Warning: skipping line {} of {} ({})", i + 1, path.display(), e),
+            }
+        }
+        Ok(Playback { events, start: None })
+    }
+
+    /// Events whose time has come since the last call.
+    pub fn due_events(&mut self) -> Vec<InputEvent> {
+        let elapsed = self.start.get_or_insert_with(Instant::now).elapsed().as_millis() as u64;
+        let mut due = Vec::new();
+        while self.events.front().is_some_and(|e| e.t <= elapsed) {
+            if let Some(e) = self.events.pop_front() {
+                due.push(e.event);
+            }
+        }
+        due
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.events.is_empty()
+    }
+}
+
+pub fn button_name(button: MouseButton) -> String {
+    match button {
+        MouseButton::Left => "left".to_string(),
+        MouseButton::Right => "right".to_string(),
+        MouseButton::Middle => "middle".to_string(),
+        MouseButton::Other(n) => format!("other{}", n),
+    }
+}
+
+pub fn button_from_name(name: &str) -> Option<MouseButton> {
+    match name {
+        "left" => Some(MouseButton::Left),
+        "right" => Some(MouseButton::Right),
+        "middle" => Some(MouseButton::Middle),
+        _ => name.strip_prefix("other").and_then(|n| n.parse().ok()).map(MouseButton::Other),
+    }
+}
@@ -0,0 +1,32 @@
+/**
+ * Small pseudo-random number generator, good enough for drills and fuzzing but not for anything that needs
+ * real randomness.
+ */
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// xorshift64, see Marsaglia, "Xorshift RNGs" (2003).
+pub struct XorShift {
+    state: u64,
+}
+
+impl XorShift {
+    /// Seeded from the clock, so every run is different.
+    pub fn from_clock() -> XorShift {
+        let seed = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64);
+        // the state must never be zero
+        XorShift { state: seed | 1 }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state ^= self.state << 13;
+        self.state ^= self.state >> 7;
+        self.state ^= self.state << 17;
+        self.state
+    }
+
+    /// Number in `0..n`, `n` being greater than zero.
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next_u64() % n as u64) as usize
+    }
+}
@@ -0,0 +1,495 @@
+/**
+ * Board analysis the GUI does on its own, independent of the engine.
+ */
+
+use murnion_chess::{Colour, Game, Piece};
+
+pub type Board = [[Piece; 8]; 8];
+
+const KNIGHT_OFFSETS: [(i8, i8); 8] = [(-2, -1), (-2, 1), (-1, -2), (-1, 2), (1, -2), (1, 2), (2, -1), (2, 1)];
+const KING_OFFSETS: [(i8, i8); 8] = [(-1, -1), (-1, 0), (-1, 1), (0, -1), (0, 1), (1, -1), (1, 0), (1, 1)];
+const ROOK_DIRECTIONS: [(i8, i8); 4] = [(-1, 0), (1, 0), (0, -1), (0, 1)];
+const BISHOP_DIRECTIONS: [(i8, i8); 4] = [(-1, -1), (-1, 1), (1, -1), (1, 1)];
+
+pub fn opponent(colour: Colour) -> Colour {
+    match colour {
+        Colour::White => Colour::Black,
+        Colour::Black => Colour::White,
+    }
+}
+
+/// Square reached by stepping `offset` from `square`, if it is still on the board.
+fn step(square: (usize, usize), offset: (i8, i8)) -> Option<(usize, usize)> {
+    let rank = square.0 as i8 + offset.0;
+    let file = square.1 as i8 + offset.1;
+    if (0..8).contains(&rank) && (0..8).contains(&file) {
+        Some((rank as usize, file as usize))
+    } else {
+        None
+    }
+}
+
+/// Every square of the board, rank by rank starting from black's back rank.
+pub fn all_squares() -> impl Iterator<Item = (usize, usize)> {
+    (0..8).flat_map(|rank| (0..8).map(move |file| (rank, file)))
+}
+
+/// First occupied square from `square` in `direction`, not counting `square` itself.
+fn first_piece(board: &Board, square: (usize, usize), direction: (i8, i8)) -> Option<(usize, usize)> {
+    let mut current = square;
+    while let Some(next) = step(current, direction) {
+        if board[next.0][next.1] != Piece::Empty {
+            return Some(next);
+        }
+        current = next;
+    }
+    None
+}
+
+/// Squares of `by`'s pieces that attack `target`.
+pub fn attackers(board: &Board, target: (usize, usize), by: Colour) -> Vec<(usize, usize)> {
+    let mut squares = Vec::new();
+
+    // white pawns attack towards rank index 0, so they sit below the target
+    let pawn_rank = if by == Colour::White { 1 } else { -1 };
+    for &offset in [(pawn_rank, -1), (pawn_rank, 1)].iter() {
+        if let Some(s) = step(target, offset) {
+            if board[s.0][s.1] == Piece::Pawn(by) {
+                squares.push(s);
+            }
+        }
+    }
+
+    for &offset in KNIGHT_OFFSETS.iter() {
+        if let Some(s) = step(target, offset) {
+            if board[s.0][s.1] == Piece::Knight(by) {
+                squares.push(s);
+            }
+        }
+    }
+
+    for &offset in KING_OFFSETS.iter() {
+        if let Some(s) = step(target, offset) {
+            if board[s.0][s.1] == Piece::King(by) {
+                squares.push(s);
+            }
+        }
+    }
+
+    for &direction in ROOK_DIRECTIONS.iter() {
+        if let Some(s) = first_piece(board, target, direction) {
+            if board[s.0][s.1] == Piece::Rook(by) || board[s.0][s.1] == Piece::Queen(by) {
+                squares.push(s);
+            }
+        }
+    }
+
+    for &direction in BISHOP_DIRECTIONS.iter() {
+        if let Some(s) = first_piece(board, target, direction) {
+            if board[s.0][s.1] == Piece::Bishop(by) || board[s.0][s.1] == Piece::Queen(by) {
+                squares.push(s);
+            }
+        }
+    }
+
+    squares
+}
+
+/// Square of `colour`'s king, if it is on the board.
+pub fn king_square(board: &Board, colour: Colour) -> Option<(usize, usize)> {
+    all_squares()
+        .find(|&(rank, file)| board[rank][file] == Piece::King(colour))
+}
+
+pub fn in_check(board: &Board, colour: Colour) -> bool {
+    match king_square(board, colour) {
+        Some(king) => !attackers(board, king, opponent(colour)).is_empty(),
+        None => false,
+    }
+}
+
+/// Squares of `colour`'s pieces that shield their king from an enemy rook, bishop or queen.
+pub fn pinned_pieces(board: &Board, colour: Colour) -> Vec<(usize, usize)> {
+    let king = match king_square(board, colour) {
+        Some(s) => s,
+        None => return Vec::new(),
+    };
+    let enemy = opponent(colour);
+    let mut pinned = Vec::new();
+
+    // a pinned piece is the first one out from the king, with a slider of the right kind right behind it
+    let lines = ROOK_DIRECTIONS.iter().map(|d| (*d, Piece::Rook(enemy)))
+        .chain(BISHOP_DIRECTIONS.iter().map(|d| (*d, Piece::Bishop(enemy))));
+    for (direction, slider) in lines {
+        if let Some(shield) = first_piece(board, king, direction) {
+            if crate::get_colour(board[shield.0][shield.1]) != Some(colour) {
+                continue;
+            }
+            if let Some(behind) = first_piece(board, shield, direction) {
+                let attacker = board[behind.0][behind.1];
+                if attacker == slider || attacker == Piece::Queen(enemy) {
+                    pinned.push(shield);
+                }
+            }
+        }
+    }
+    pinned
+}
+
+/// Exactly one king per side and no pawns on the first or last rank.
+pub fn check_board(board: &Board) -> Result<(), String> {
+    for &colour in [Colour::White, Colour::Black].iter() {
+        let kings = board.iter().flat_map(|row| row.iter()).filter(|&&p| p == Piece::King(colour)).count();
+        if kings != 1 {
+            return Err(format!("{} {} kings on the board", kings, if colour == Colour::White { "white" } else { "black" }));
+        }
+    }
+    for &rank in [0, 7].iter() {
+        if board[rank].iter().any(|p| matches!(p, Piece::Pawn(_))) {
+            return Err(format!("a pawn on rank {}", 8 - rank));
+        }
+    }
+    Ok(())
+}
+
+/// Rules on top of the engine's that decide which moves the GUI offers and accepts.
+#[derive(Clone, Copy)]
+pub struct MoveRules {
+    /// The antichess king may walk into check, but never castles.
+    pub antichess: bool,
+    /// Teaching restrictions, see `AppConfig::allow_castling`.
+    pub allow_castling: bool,
+    pub allow_en_passant: bool,
+    /// Castling rights in "KQkq" order, fewer than the engine assumes for some positions loaded from FEN.
+    pub castling_rights: [bool; 4],
+}
+
+impl Default for MoveRules {
+    /// Standard chess with every castling right.
+    fn default() -> MoveRules {
+        MoveRules { antichess: false, allow_castling: true, allow_en_passant: true, castling_rights: [true; 4] }
+    }
+}
+
+/// Moves of the piece on `from` under `rules`, as if it were its side's turn, before the antichess capture rule.
+pub fn legal_moves(game: &Game, from: (usize, usize), rules: &MoveRules) -> Vec<(usize, usize)> {
+    let piece = game.board[from.0][from.1];
+    let colour = match crate::get_colour(piece) {
+        Some(c) => c,
+        None => return Vec::new(),
+    };
+    piece.get_valid_moves(from, &game.board, game.en_passant_square, game.castlings, colour)
+        .into_iter()
+        .filter(|&to| rules.antichess || is_safe_move(&game.board, from, to, colour))
+        .filter(|&to| match piece {
+            Piece::King(_) if rules.antichess || !rules.allow_castling => crate::castling_index(from, to).is_none(),
+            Piece::King(_) => crate::castling_index(from, to).map_or(true, |i| rules.castling_rights[i]),
+            // only an en passant capture can land on the en passant square
+            Piece::Pawn(_) if !rules.allow_en_passant => Some(to) != game.en_passant_square,
+            _ => true,
+        })
+        .collect()
+}
+
+/// Board after moving the piece on `from` to `to`, including en passant captures and castling rook moves.
+pub fn apply_move(board: &Board, from: (usize, usize), to: (usize, usize)) -> Board {
+    let mut next = *board;
+    let piece = board[from.0][from.1];
+
+    // a pawn moving diagonally onto an empty square captures en passant
+    if let Piece::Pawn(_) = piece {
+        if from.1 != to.1 && board[to.0][to.1] == Piece::Empty {
+            next[from.0][to.1] = Piece::Empty;
+        }
+    }
+
+    // a king moving two files castles, bringing the rook along
+    if let Piece::King(_) = piece {
+        if from.1 == 4 && (to.1 == 6 || to.1 == 2) {
+            let (rook_from, rook_to) = if to.1 == 6 { (7, 5) } else { (0, 3) };
+            next[from.0][rook_to] = next[from.0][rook_from];
+            next[from.0][rook_from] = Piece::Empty;
+        }
+    }
+
+    next[to.0][to.1] = piece;
+    next[from.0][from.1] = Piece::Empty;
+    next
+}
+
+/// Whether a move the piece can make leaves its own king safe, including not castling out of or through check.
+pub fn is_safe_move(board: &Board, from: (usize, usize), to: (usize, usize), colour: Colour) -> bool {
+    if let Piece::King(_) = board[from.0][from.1] {
+        if from.1 == 4 && (to.1 == 6 || to.1 == 2) {
+            let passed = (from.0, if to.1 == 6 { 5 } else { 3 });
+            if in_check(board, colour) || !attackers(board, passed, opponent(colour)).is_empty() {
+                return false;
+            }
+        }
+    }
+
+    !in_check(&apply_move(board, from, to), colour)
+}
+
+/// Square of the piece taken by moving from `from` to `to`, if the move captures, including en passant.
+pub fn captured_square(board: &Board, from: (usize, usize), to: (usize, usize)) -> Option<(usize, usize)> {
+    if board[to.0][to.1] != Piece::Empty {
+        Some(to)
+    } else if matches!(board[from.0][from.1], Piece::Pawn(_)) && to.1 != from.1 {
+        // diagonal pawn move onto an empty square takes the pawn beside it
+        Some((from.0, to.1))
+    } else {
+        None
+    }
+}
+
+/// The moves among `moves` of the piece on `from` that capture, all it may play in antichess while any capture is possible.
+pub fn captures_only(board: &Board, from: (usize, usize), moves: Vec<(usize, usize)>) -> Vec<(usize, usize)> {
+    moves.into_iter().filter(|&to| captured_square(board, from, to).is_some()).collect()
+}
+
+/// Whether a capturing piece would be left at least as well defended as it is attacked on its new square.
+/// A counting heuristic only: piece values and the order of exchanges, as a full static exchange evaluation
+/// would weigh them, are ignored.
+pub fn capture_is_safe(board: &Board, from: (usize, usize), to: (usize, usize), colour: Colour) -> bool {
+    let next = apply_move(board, from, to);
+    attackers(&next, to, opponent(colour)).len() <= attackers(&next, to, colour).len()
+}
+
+/// Whether neither side can ever deliver mate, for the one case detected: only kings and pawns, every pawn
+/// locked head-on by an enemy pawn with nothing to capture, and neither king able to reach an undefended enemy pawn.
+/// Other dead positions, such as those with locked bishops, are not recognised.
+pub fn is_dead_position(board: &Board) -> bool {
+    for (rank, file) in all_squares() {
+        match board[rank][file] {
+            Piece::Empty | Piece::King(_) => {}
+            Piece::Pawn(c) => {
+                let forward = if c == Colour::White { -1 } else { 1 };
+                let enemy = Piece::Pawn(opponent(c));
+                match step((rank, file), (forward, 0)) {
+                    Some(s) if board[s.0][s.1] == enemy => {}
+                    _ => return false,
+                }
+                for &side in [-1, 1].iter() {
+                    if let Some(s) = step((rank, file), (forward, side)) {
+                        if board[s.0][s.1] == enemy {
+                            return false;
+                        }
+                    }
+                }
+            }
+            _ => return false,
+        }
+    }
+
+    [Colour::White, Colour::Black].iter().all(|&c| !king_can_win_pawn(board, c))
+}
+
+/// Whether `colour`'s king can walk to and capture an enemy pawn that no other enemy pawn defends.
+fn king_can_win_pawn(board: &Board, colour: Colour) -> bool {
+    let start = match king_square(board, colour) {
+        Some(s) => s,
+        None => return false,
+    };
+    let enemy = opponent(colour);
+    let pawn_attacked = |s: (usize, usize)| attackers(board, s, enemy).iter().any(|a| board[a.0][a.1] == Piece::Pawn(enemy));
+
+    // flood fill the squares the king can walk over, never stepping onto a square an enemy pawn covers
+    let mut reached = [[false; 8]; 8];
+    let mut queue = vec![start];
+    reached[start.0][start.1] = true;
+    while let Some(current) = queue.pop() {
+        for &offset in KING_OFFSETS.iter() {
+            if let Some(s) = step(current, offset) {
+                if board[s.0][s.1] == Piece::Pawn(enemy) && !pawn_attacked(s) {
+                    return true;
+                }
+                if !reached[s.0][s.1] && board[s.0][s.1] == Piece::Empty && !pawn_attacked(s) {
+                    reached[s.0][s.1] = true;
+                    queue.push(s);
+                }
+            }
+        }
+    }
+    false
+}
+
+/// Board with only the given pieces, placed by algebraic square name, for the tests of any module.
+#[cfg(test)]
+pub(crate) fn board_with(pieces: &[(&str, Piece)]) -> Board {
+    let mut board = [[Piece::Empty; 8]; 8];
+    for &(name, piece) in pieces {
+        let s = crate::notation::square_from_algebraic(name).unwrap();
+        board[s.0][s.1] = piece;
+    }
+    board
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notation::square_from_algebraic;
+
+    fn with_kings(pieces: &[(&str, Piece)]) -> Board {
+        let mut all = vec![("a1", Piece::King(Colour::White)), ("h8", Piece::King(Colour::Black))];
+        all.extend_from_slice(pieces);
+        board_with(&all)
+    }
+
+    /// Starting position with the pieces between the white king and its rooks taken off.
+    fn castling_position() -> Game {
+        let mut game = Game::new();
+        for &file in [1, 2, 3, 5, 6].iter() {
+            game.board[7][file] = Piece::Empty;
+        }
+        game
+    }
+
+    /// Starting position with the pieces between the black king and its rooks taken off.
+    fn black_castling_position() -> Game {
+        let mut game = Game::new();
+        for &file in [1, 2, 3, 5, 6].iter() {
+            game.board[0][file] = Piece::Empty;
+        }
+        game.current_turn = Colour::Black;
+        game
+    }
+
+    #[test]
+    fn castling_offered_by_default() {
+        let moves = legal_moves(&castling_position(), (7, 4), &MoveRules::default());
+        assert!(moves.contains(&(7, 6)) && moves.contains(&(7, 2)));
+    }
+
+    #[test]
+    fn castling_off_removes_the_castling_squares() {
+        let rules = MoveRules { allow_castling: false, ..MoveRules::default() };
+        let moves = legal_moves(&castling_position(), (7, 4), &rules);
+        assert!(!moves.contains(&(7, 6)) && !moves.contains(&(7, 2)));
+        assert!(moves.contains(&(7, 5)) && moves.contains(&(7, 3)));
+    }
+
+    #[test]
+    fn castling_rights_limit_the_castling_squares() {
+        let rules = MoveRules { castling_rights: [false, true, true, true], ..MoveRules::default() };
+        let moves = legal_moves(&castling_position(), (7, 4), &rules);
+        assert!(!moves.contains(&(7, 6)) && moves.contains(&(7, 2)));
+    }
+
+    #[test]
+    fn black_castling_offered_on_both_sides() {
+        let moves = legal_moves(&black_castling_position(), (0, 4), &MoveRules::default());
+        assert!(moves.contains(&(0, 6)) && moves.contains(&(0, 2)));
+    }
+
+    #[test]
+    fn black_castling_rights_limit_the_castling_squares() {
+        let kingside = MoveRules { castling_rights: [true, true, true, false], ..MoveRules::default() };
+        let moves = legal_moves(&black_castling_position(), (0, 4), &kingside);
+        assert!(moves.contains(&(0, 6)) && !moves.contains(&(0, 2)));
+
+        let queenside = MoveRules { castling_rights: [true, true, false, true], ..MoveRules::default() };
+        let moves = legal_moves(&black_castling_position(), (0, 4), &queenside);
+        assert!(!moves.contains(&(0, 6)) && moves.contains(&(0, 2)));
+    }
+
+    #[test]
+    fn en_passant_off_removes_the_capture() {
+        let mut game = Game::new();
+        game.board[6][4] = Piece::Empty;
+        game.board[1][3] = Piece::Empty;
+        game.board[3][4] = Piece::Pawn(Colour::White);
+        game.board[3][3] = Piece::Pawn(Colour::Black);
+        game.en_passant_square = Some((2, 3));
+        assert!(legal_moves(&game, (3, 4), &MoveRules::default()).contains(&(2, 3)));
+
+        let rules = MoveRules { allow_en_passant: false, ..MoveRules::default() };
+        assert_eq!(legal_moves(&game, (3, 4), &rules), vec![(2, 4)]);
+    }
+
+    #[test]
+    fn check_board_wants_one_king_each_and_no_pawns_on_the_back_ranks() {
+        assert!(check_board(&with_kings(&[])).is_ok());
+        assert!(check_board(&board_with(&[("a1", Piece::King(Colour::White))])).is_err());
+        assert!(check_board(&with_kings(&[("c3", Piece::King(Colour::White))])).is_err());
+        assert!(check_board(&with_kings(&[("d8", Piece::Pawn(Colour::White))])).is_err());
+    }
+
+    #[test]
+    fn captures_only_keeps_captures() {
+        let board = board_with(&[
+            ("d4", Piece::Rook(Colour::White)),
+            ("d7", Piece::Pawn(Colour::Black)),
+            ("g4", Piece::Knight(Colour::Black)),
+        ]);
+        let s = |name| square_from_algebraic(name).unwrap();
+        let moves = vec![s("d5"), s("d7"), s("a4"), s("g4")];
+        assert_eq!(captures_only(&board, s("d4"), moves), vec![s("d7"), s("g4")]);
+    }
+
+    #[test]
+    fn captures_only_keeps_en_passant() {
+        let board = board_with(&[("e5", Piece::Pawn(Colour::White)), ("d5", Piece::Pawn(Colour::Black))]);
+        let s = |name| square_from_algebraic(name).unwrap();
+        assert_eq!(captures_only(&board, s("e5"), vec![s("e6"), s("d6")]), vec![s("d6")]);
+    }
+
+    #[test]
+    fn locked_pawn_chain_is_dead() {
+        let white = ["a3", "b4", "c3", "d4", "e3", "f4", "g3", "h4"];
+        let black = ["a4", "b5", "c4", "d5", "e4", "f5", "g4", "h5"];
+        let mut pieces = vec![("e1", Piece::King(Colour::White)), ("e8", Piece::King(Colour::Black))];
+        pieces.extend(white.iter().map(|&s| (s, Piece::Pawn(Colour::White))));
+        pieces.extend(black.iter().map(|&s| (s, Piece::Pawn(Colour::Black))));
+        assert!(is_dead_position(&board_with(&pieces)));
+    }
+
+    #[test]
+    fn pawn_capture_is_not_dead() {
+        let board = with_kings(&[
+            ("d4", Piece::Pawn(Colour::White)),
+            ("e4", Piece::Pawn(Colour::White)),
+            ("d5", Piece::Pawn(Colour::Black)),
+            ("e5", Piece::Pawn(Colour::Black)),
+        ]);
+        assert!(!is_dead_position(&board));
+    }
+
+    #[test]
+    fn king_reaching_undefended_pawn_is_not_dead() {
+        let board = with_kings(&[("e4", Piece::Pawn(Colour::White)), ("e5", Piece::Pawn(Colour::Black))]);
+        assert!(king_can_win_pawn(&board, Colour::White));
+        assert!(!is_dead_position(&board));
+    }
+
+    #[test]
+    fn knight_pinned_by_bishop() {
+        let board = board_with(&[
+            ("e1", Piece::King(Colour::White)),
+            ("d2", Piece::Knight(Colour::White)),
+            ("b4", Piece::Bishop(Colour::Black)),
+        ]);
+        assert_eq!(pinned_pieces(&board, Colour::White), vec![square_from_algebraic("d2").unwrap()]);
+    }
+
+    #[test]
+    fn second_piece_on_the_line_is_no_pin() {
+        let board = board_with(&[
+            ("e1", Piece::King(Colour::White)),
+            ("d2", Piece::Knight(Colour::White)),
+            ("c3", Piece::Pawn(Colour::White)),
+            ("b4", Piece::Bishop(Colour::Black)),
+        ]);
+        assert!(pinned_pieces(&board, Colour::White).is_empty());
+    }
+
+    #[test]
+    fn rook_on_a_diagonal_is_no_pin() {
+        let board = board_with(&[
+            ("e1", Piece::King(Colour::White)),
+            ("d2", Piece::Knight(Colour::White)),
+            ("b4", Piece::Rook(Colour::Black)),
+        ]);
+        assert!(pinned_pieces(&board, Colour::White).is_empty());
+    }
+}
@@ -0,0 +1,78 @@
+/**
+ * Sound effects for picking up and putting down pieces.
+ */
+
+use ggez::{audio::{self, SoundSource}, Context};
+
+const LIFT_FILE: &str = "/lift.wav";
+const DROP_FILE: &str = "/drop.wav";
+
+/// Volume steps taken by the volume keys.
+pub const VOLUME_STEP: f32 = 0.1;
+
+#[derive(Clone, Copy)]
+pub enum Effect {
+    /// A piece is picked up.
+    Lift,
+    /// A piece is put down on its new square.
+    Drop,
+}
+
+pub struct Sounds {
+    lift: audio::Source,
+    drop: audio::Source,
+}
+
+impl Sounds {
+    /// Loads the effects from the resource directory, or `None` with a warning so the game goes on without sound.
+    pub fn load(ctx: &mut Context) -> Option<Sounds> {
+        match (audio::Source::new(ctx, LIFT_FILE), audio::Source::new(ctx, DROP_FILE)) {
+            (Ok(lift), Ok(drop)) => Some(Sounds { lift, drop }),
+            (Err(e), _) | (_, Err(e)) => {
+                eprintln!("Warning: could not load the sound effects, playing without sound ({})", e);
+                None
+            },
+        }
+    }
+
+    /// Plays `effect` at `volume`, between 0 and 1, over any sound still playing.
+    pub fn play(&mut self, effect: Effect, volume: f32) {
+        let source = match effect {
+            Effect::Lift => &mut self.lift,
+            Effect::Drop => &mut self.drop,
+        };
+        source.set_volume(volume);
+        if let Err(e) = source.play_detached() {
+            eprintln!("Warning: could not play a sound ({})", e);
+        }
+    }
+}
+
+/// `volume` moved one step up or down, kept between 0 and 1.
+pub fn step_volume(volume: f32, up: bool) -> f32 {
+    let step = if up { VOLUME_STEP } else { -VOLUME_STEP };
+    // rounded to whole steps so repeated presses don't drift
+    (((volume + step) / VOLUME_STEP).round() * VOLUME_STEP).clamp(0.0, 1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn volume_steps_stay_between_silent_and_full() {
+        assert_eq!(step_volume(1.0, true), 1.0);
+        assert_eq!(step_volume(0.0, false), 0.0);
+        let mut volume = 0.0;
+        for _ in 0..10 {
+            volume = step_volume(volume, true);
+        }
+        assert_eq!(volume, 1.0);
+    }
+
+    #[test]
+    fn volume_steps_land_on_whole_steps() {
+        assert!((step_volume(0.5, true) - 0.6).abs() < 1e-6);
+        assert!((step_volume(0.52, false) - 0.4).abs() < 1e-6);
+    }
+}
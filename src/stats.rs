@@ -0,0 +1,67 @@
+/**
+ * Lifetime statistics of finished games, kept in the resource directory.
+ */
+
+use murnion_chess::Colour;
+use serde::{Deserialize, Serialize};
+use std::{fs, path::Path};
+
+/// Name of the statistics file inside the resource directory.
+pub const STATS_FILE: &str = "stats.json";
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct Stats {
+    pub games_finished: u32,
+    pub white_wins: u32,
+    pub black_wins: u32,
+    pub draws: u32,
+    /// Sum of the lengths of all finished games, in moves.
+    pub total_moves: u32,
+    /// Most squares found in one coordinate quiz round.
+    pub quiz_best: u32,
+}
+
+impl Stats {
+    /// Reads the statistics, starting from zero if there are none or they can't be read.
+    pub fn load(resource_dir: &Path) -> Stats {
+        let file = resource_dir.join(STATS_FILE);
+        if !file.exists() {
+            return Stats::default();
+        }
+
+        match fs::read_to_string(&file).map_err(|e| e.to_string())
+            .and_then(|s| serde_json::from_str(&s).map_err(|e| e.to_string())) {
+            Ok(stats) => stats,
+            Err(e) => {
+                eprintln!("Warning: could not read {}, resetting statistics ({})", file.display(), e);
+                Stats::default()
+            }
+        }
+    }
+
+    pub fn save(&self, resource_dir: &Path) {
+        let file = resource_dir.join(STATS_FILE);
+        let result = serde_json::to_string_pretty(self)
+            .map_err(|e| e.to_string())
+            .and_then(|s| fs::write(&file, s).map_err(|e| e.to_string()));
+        if let Err(e) = result {
+            eprintln!("Warning: could not save {} ({})", file.display(), e);
+        }
+    }
+
+    /// Counts a finished game, `winner` being `None` for a draw.
+    pub fn record(&mut self, winner: Option<Colour>, moves: u32) {
+        self.games_finished += 1;
+        self.total_moves += moves;
+        match winner {
+            Some(Colour::White) => self.white_wins += 1,
+            Some(Colour::Black) => self.black_wins += 1,
+            None => self.draws += 1,
+        }
+    }
+
+    pub fn average_moves(&self) -> u32 {
+        self.total_moves.checked_div(self.games_finished).unwrap_or(0)
+    }
+}
@@ -0,0 +1,13 @@
+/**
+ * Helpers shared by the tests of several modules.
+ */
+
+use std::{env, fs, path::PathBuf, process};
+
+/// Empty directory of its own for a test, under the system temporary directory.
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("schack_gui_{}_{}", name, process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}